@@ -0,0 +1,270 @@
+use crate::lexer::types::{QuoteType, Word, WordPart};
+
+// A longer sequence is reported and left unexpanded instead of exhausting
+// memory
+const MAX_SEQUENCE: u64 = 1 << 20;
+
+/// A word broken down for brace expansion: unquoted literal text is kept
+/// character by character, anything quoted or expanded stays whole.
+#[derive(Debug, Clone)]
+enum Piece {
+    Char(char),
+    Part(WordPart),
+}
+
+/// Brace expansion of one word, done before any other expansion.
+///
+/// `a{b,c}d` becomes `abd acd`, `{1..10..2}` and `{a..e}` produce sequences
+/// and `{01..10}` pads numbers to the same width. Braces that are quoted,
+/// unmatched or hold neither a comma nor a valid range are left as they are.
+/// So is a range of more than 2^20 terms, with a message on stderr.
+pub fn expand_braces(word: &Word) -> Vec<Word> {
+    let mut pieces = Vec::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal((text, QuoteType::None)) => {
+                pieces.extend(text.chars().map(Piece::Char));
+            }
+            _ => pieces.push(Piece::Part(part.clone())),
+        }
+    }
+
+    expand(&pieces)
+        .into_iter()
+        .map(|pieces| Word {
+            parts: collect_parts(pieces),
+            quote: word.quote,
+        })
+        .collect()
+}
+
+fn expand(pieces: &[Piece]) -> Vec<Vec<Piece>> {
+    let mut start = 0;
+    while let Some(open) = find_char(pieces, start, '{') {
+        start = open + 1;
+        let Some(close) = matching_brace(pieces, open) else {
+            continue;
+        };
+        let body = &pieces[open + 1..close];
+
+        let alternatives = match sequence(body) {
+            Some(items) => items
+                .into_iter()
+                .map(|item| item.chars().map(Piece::Char).collect())
+                .collect(),
+            None => {
+                let alternatives = split_commas(body);
+                if alternatives.len() < 2 {
+                    continue;
+                }
+                alternatives
+            }
+        };
+
+        let prefix = &pieces[..open];
+        let suffix = &pieces[close + 1..];
+        let mut words = Vec::new();
+        for alternative in alternatives {
+            let mut combined = prefix.to_vec();
+            combined.extend(alternative);
+            combined.extend_from_slice(suffix);
+            words.extend(expand(&combined));
+        }
+        return words;
+    }
+    vec![pieces.to_vec()]
+}
+
+fn find_char(pieces: &[Piece], from: usize, wanted: char) -> Option<usize> {
+    (from..pieces.len()).find(|&i| matches!(pieces[i], Piece::Char(c) if c == wanted))
+}
+
+fn matching_brace(pieces: &[Piece], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, piece) in pieces.iter().enumerate().skip(open) {
+        match piece {
+            Piece::Char('{') => depth += 1,
+            Piece::Char('}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the inside of a brace pair on commas that are not nested.
+fn split_commas(body: &[Piece]) -> Vec<Vec<Piece>> {
+    let mut alternatives = vec![Vec::new()];
+    let mut depth = 0;
+    for piece in body {
+        match piece {
+            Piece::Char('{') => depth += 1,
+            Piece::Char('}') => depth -= 1,
+            Piece::Char(',') if depth == 0 => {
+                alternatives.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(current) = alternatives.last_mut() {
+            current.push(piece.clone());
+        }
+    }
+    alternatives
+}
+
+/// Expands `x..y` or `x..y..step` where both ends are integers or single
+/// characters.
+fn sequence(body: &[Piece]) -> Option<Vec<String>> {
+    let mut text = String::new();
+    for piece in body {
+        match piece {
+            Piece::Char(c) => text.push(*c),
+            Piece::Part(_) => return None,
+        }
+    }
+
+    let fields: Vec<&str> = text.split("..").collect();
+    let step = match fields.len() {
+        2 => 1,
+        3 => fields[2].parse::<i64>().ok()?.unsigned_abs().max(1),
+        _ => return None,
+    };
+    let (first, last) = (fields[0], fields[1]);
+
+    if let (Ok(from), Ok(to)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        let width = if is_padded(first) || is_padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        return Some(
+            range(from, to, step)?
+                .map(|n| {
+                    if n < 0 {
+                        format!("-{:0>1$}", n.unsigned_abs(), width.saturating_sub(1))
+                    } else {
+                        format!("{:0>1$}", n, width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let mut first_chars = first.chars();
+    let mut last_chars = last.chars();
+    match (
+        first_chars.next(),
+        first_chars.next(),
+        last_chars.next(),
+        last_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None) if from.is_ascii() && to.is_ascii() => Some(
+            range(from as i64, to as i64, step)?
+                .filter_map(|n| char::from_u32(n as u32))
+                .map(String::from)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn is_padded(number: &str) -> bool {
+    let digits = number.strip_prefix('-').unwrap_or(number);
+    digits.len() > 1 && digits.starts_with('0')
+}
+
+/// The numbers from `from` to `to` every `step`, `None` after a message
+/// when there are more than `MAX_SEQUENCE` of them.
+fn range(from: i64, to: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (from.abs_diff(to) / step).saturating_add(1);
+    if count > MAX_SEQUENCE {
+        eprintln!(
+            "brace expansion: {}..{} has more than {} terms, left unexpanded",
+            from, to, MAX_SEQUENCE
+        );
+        return None;
+    }
+    // every term lies between `from` and `to`, only the product needs room
+    let step = if from <= to { step as i128 } else { -(step as i128) };
+    Some((0..count as i128).map(move |i| (from as i128 + i * step) as i64))
+}
+
+fn collect_parts(pieces: Vec<Piece>) -> Vec<WordPart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Char(c) => text.push(c),
+            Piece::Part(part) => {
+                if !text.is_empty() {
+                    parts.push(WordPart::Literal((std::mem::take(&mut text), QuoteType::None)));
+                }
+                parts.push(part);
+            }
+        }
+    }
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(WordPart::Literal((text, QuoteType::None)));
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_text(text: &str) -> Vec<String> {
+        let word = Word {
+            parts: vec![WordPart::Literal((text.to_string(), QuoteType::None))],
+            quote: QuoteType::None,
+        };
+        expand_braces(&word)
+            .iter()
+            .map(|word| match word.parts.as_slice() {
+                [WordPart::Literal((text, _))] => text.clone(),
+                parts => panic!("unexpected parts: {:?}", parts),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn alternatives_and_nesting() {
+        assert_eq!(expand_text("a{b,c}d"), ["abd", "acd"]);
+        assert_eq!(expand_text("{a,b{1,2}}"), ["a", "b1", "b2"]);
+        assert_eq!(expand_text("{x}"), ["{x}"]);
+        assert_eq!(expand_text("{a,b"), ["{a,b"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand_text("{1..10..3}"), ["1", "4", "7", "10"]);
+        assert_eq!(expand_text("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand_text("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand_text("{08..10}"), ["08", "09", "10"]);
+        assert_eq!(expand_text("{-02..1}"), ["-02", "-01", "000", "001"]);
+    }
+
+    #[test]
+    fn ranges_at_the_integer_limits() {
+        let (min, max) = (i64::MIN, i64::MAX);
+        let full = format!("{{{}..{}}}", min, max);
+        assert_eq!(expand_text(&full), [full.as_str()]);
+
+        let wide_step = format!("{{{}..{}..{}}}", min, max, min);
+        assert_eq!(expand_text(&wide_step), [min.to_string(), "0".to_string()]);
+
+        let end = format!("{{{}..{}}}", max - 1, max);
+        assert_eq!(expand_text(&end), [(max - 1).to_string(), max.to_string()]);
+    }
+
+    #[test]
+    fn ranges_over_the_limit_are_left_unexpanded() {
+        let too_long = format!("{{1..{}}}", MAX_SEQUENCE + 1);
+        assert_eq!(expand_text(&too_long), [too_long.as_str()]);
+    }
+}
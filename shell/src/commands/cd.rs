@@ -20,7 +20,7 @@ impl Cd {
 }   
 
 impl ShellCommand for Cd {
-    fn execute(&self, _env: &mut ShellEnv) -> Result<i32, ShellError> {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
         let target_dir: PathBuf;
 
         if self.args.is_empty() {
@@ -45,7 +45,12 @@ impl ShellCommand for Cd {
             return Err(ShellError::Exec(String::from("Directory does not exist")));
         }
 
+        let old_dir = env::current_dir()?;
         env::set_current_dir(&target_dir)?;
+
+        // kept up to date for `~+` and `~-`
+        env.set_env_var("OLDPWD", &old_dir.to_string_lossy());
+        env.set_env_var("PWD", &env::current_dir()?.to_string_lossy());
         Ok(0)
     }
 }
@@ -8,22 +8,27 @@ pub struct Exit {
 }
 
 impl Exit {
-    pub fn new(mut args: Vec<String>, opts: Vec<String>) -> Self {
-        if args.is_empty() {
-            args.push("0".to_string());
-        }
+    pub fn new(args: Vec<String>, opts: Vec<String>) -> Self {
         Exit { args, opts }
     }
 }
 
 impl ShellCommand for Exit {
-    fn execute(&self, _env: &mut ShellEnv) -> Result<i32, ShellError> {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
         if self.args.len() > 1 {
             return Err(ShellError::Exec(String::from("Exit command accepts at most one argument")));
         }
-        let exit_code: i32 = self.args.get(0)
-            .and_then(|s| s.parse::<i32>().ok())
-            .unwrap_or(0);
+        // without an argument the shell exits with the last command's status
+        let exit_code: i32 = match self.args.first() {
+            Some(arg) => match arg.parse::<i32>() {
+                Ok(code) => code,
+                Err(_) => {
+                    eprintln!("exit: {}: numeric argument required", arg);
+                    2
+                }
+            },
+            None => env.get_last_status(),
+        };
 
         std::process::exit(exit_code);
     }
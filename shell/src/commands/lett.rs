@@ -0,0 +1,34 @@
+use crate::{ShellCommand, envirement::ShellEnv, error::ShellError, eval::eval_arith_str};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Let {
+    pub args: Vec<String>,
+}
+
+impl Let {
+    pub fn new(args: Vec<String>) -> Self {
+        Let { args }
+    }
+}
+
+impl ShellCommand for Let {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        if self.args.is_empty() {
+            eprintln!("let: expression expected");
+            return Ok(1);
+        }
+
+        // Each argument is its own expression, the status comes from the last one
+        let mut last = 0;
+        for arg in &self.args {
+            match eval_arith_str(arg, env) {
+                Ok(value) => last = value,
+                Err(e) => {
+                    eprintln!("let: {}", e);
+                    return Ok(1);
+                }
+            }
+        }
+        Ok(if last != 0 { 0 } else { 1 })
+    }
+}
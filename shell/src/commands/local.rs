@@ -0,0 +1,42 @@
+use crate::{ShellCommand, envirement::{ShellEnv, is_name}, error::ShellError};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Local {
+    pub args: Vec<String>,
+}
+
+impl Local {
+    pub fn new(args: Vec<String>) -> Self {
+        Local { args }
+    }
+}
+
+impl ShellCommand for Local {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let mut status = 0;
+        for arg in &self.args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            if !is_name(name) {
+                eprintln!("local: `{}': not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+            if !env.make_local(name) {
+                eprintln!("local: can only be used in a function");
+                return Ok(1);
+            }
+            // `local name` alone starts the variable out unset
+            match value {
+                Some(value) => env.set_local_var(name, value),
+                None => {
+                    env.variables.remove(name);
+                    env.arith_vars.remove(name);
+                }
+            }
+        }
+        Ok(status)
+    }
+}
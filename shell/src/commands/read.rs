@@ -0,0 +1,133 @@
+use crate::{ShellCommand, envirement::ShellEnv, error::ShellError};
+use nix::errno::Errno;
+use std::os::fd::RawFd;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Read {
+    pub args: Vec<String>,
+}
+
+impl Read {
+    pub fn new(args: Vec<String>) -> Self {
+        Read { args }
+    }
+}
+
+/// Reads one line from `fd` a byte at a time, so everything after the
+/// newline is left for whoever reads next. Gives the line without its
+/// newline and whether one was found, `None` once the input is exhausted.
+pub fn read_line(fd: RawFd) -> Option<(String, bool)> {
+    let mut bytes = Vec::new();
+    let mut byte = [0u8; 1];
+    let newline = loop {
+        match nix::unistd::read(fd, &mut byte) {
+            Ok(0) => break false,
+            Ok(_) if byte[0] == b'\n' => break true,
+            Ok(_) => bytes.push(byte[0]),
+            Err(Errno::EINTR) => continue,
+            Err(_) => break false,
+        }
+    };
+    if bytes.is_empty() && !newline {
+        return None;
+    }
+    Some((String::from_utf8_lossy(&bytes).into_owned(), newline))
+}
+
+impl ShellCommand for Read {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let mut raw = false;
+        let mut names = self.args.as_slice();
+        while let Some(arg) = names.first() {
+            match arg.as_str() {
+                "-r" => raw = true,
+                "--" => {
+                    names = &names[1..];
+                    break;
+                }
+                arg if arg.len() > 1 && arg.starts_with('-') => {
+                    eprintln!("read: {}: invalid option", arg);
+                    return Ok(2);
+                }
+                _ => break,
+            }
+            names = &names[1..];
+        }
+
+        // without -r a backslash quotes the next character and one at the
+        // end of the line continues it on the next
+        let mut chars: Vec<(char, bool)> = Vec::new();
+        let mut found_newline;
+        loop {
+            let Some((line, newline)) = read_line(0) else {
+                found_newline = false;
+                break;
+            };
+            found_newline = newline;
+            let mut iter = line.chars();
+            let mut continued = false;
+            while let Some(c) = iter.next() {
+                if c == '\\' && !raw {
+                    match iter.next() {
+                        Some(next) => chars.push((next, true)),
+                        None => continued = true,
+                    }
+                } else {
+                    chars.push((c, false));
+                }
+            }
+            if !continued || !newline {
+                break;
+            }
+        }
+
+        if names.is_empty() {
+            let line: String = chars.iter().map(|&(c, _)| c).collect();
+            env.set_local_var("REPLY", &line);
+            return Ok(if found_newline { 0 } else { 1 });
+        }
+
+        let ifs = if env.is_set("IFS") {
+            env.get("IFS").unwrap_or_default()
+        } else {
+            " \t\n".to_string()
+        };
+        let is_ifs = |&(c, quoted): &(char, bool)| !quoted && ifs.contains(c);
+        let is_ifs_space = |&(c, quoted): &(char, bool)| {
+            !quoted && matches!(c, ' ' | '\t' | '\n') && ifs.contains(c)
+        };
+
+        let mut rest = chars.as_slice();
+        while rest.first().is_some_and(is_ifs_space) {
+            rest = &rest[1..];
+        }
+        while rest.last().is_some_and(is_ifs_space) {
+            rest = &rest[..rest.len() - 1];
+        }
+        for (i, name) in names.iter().enumerate() {
+            // the last name takes whatever is left of the line
+            let end = if i + 1 == names.len() {
+                rest.len()
+            } else {
+                rest.iter().position(is_ifs).unwrap_or(rest.len())
+            };
+            let value: String = rest[..end].iter().map(|&(c, _)| c).collect();
+            env.set_local_var(name, &value);
+            rest = &rest[end..];
+
+            // one separator is the surrounding whitespace plus at most one other character
+            let mut hard = false;
+            while let Some(first) = rest.first() {
+                if is_ifs_space(first) {
+                    rest = &rest[1..];
+                } else if !hard && is_ifs(first) {
+                    hard = true;
+                    rest = &rest[1..];
+                } else {
+                    break;
+                }
+            }
+        }
+        Ok(if found_newline { 0 } else { 1 })
+    }
+}
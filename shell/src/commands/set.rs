@@ -0,0 +1,69 @@
+use crate::{ShellCommand, envirement::ShellEnv, error::ShellError};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Set {
+    pub args: Vec<String>,
+}
+
+impl Set {
+    pub fn new(args: Vec<String>) -> Self {
+        Set { args }
+    }
+}
+
+impl ShellCommand for Set {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        // `set` alone lists the shell variables
+        if self.args.is_empty() {
+            let mut variables: Vec<_> = env.variables.iter().collect();
+            variables.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, (value, _)) in variables {
+                println!("{}={}", name, value);
+            }
+            return Ok(0);
+        }
+
+        let mut args = self.args.as_slice();
+        let mut replace = false;
+        while let Some(arg) = args.first() {
+            match arg.as_str() {
+                "--" => {
+                    args = &args[1..];
+                    replace = true;
+                    break;
+                }
+                // `-o name` turns an option on and `+o name` off, alone they list them
+                "-o" | "+o" => {
+                    let enable = arg == "-o";
+                    let Some(name) = args.get(1) else {
+                        if enable {
+                            println!("pipefail\t{}", if env.pipefail { "on" } else { "off" });
+                        } else {
+                            println!("set {}o pipefail", if env.pipefail { '-' } else { '+' });
+                        }
+                        return Ok(0);
+                    };
+                    match name.as_str() {
+                        "pipefail" => env.pipefail = enable,
+                        _ => {
+                            eprintln!("set: {}: invalid option name", name);
+                            return Ok(2);
+                        }
+                    }
+                    args = &args[2..];
+                }
+                arg if arg.len() > 1 && (arg.starts_with('-') || arg.starts_with('+')) => {
+                    eprintln!("set: {}: invalid option", arg);
+                    return Ok(2);
+                }
+                _ => break,
+            }
+        }
+
+        // the remaining arguments replace the positional parameters
+        if replace || !args.is_empty() {
+            env.positional = args.to_vec();
+        }
+        Ok(0)
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{ShellCommand, envirement::ShellEnv, error::ShellError};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Shift {
+    pub args: Vec<String>,
+}
+
+impl Shift {
+    pub fn new(args: Vec<String>) -> Self {
+        Shift { args }
+    }
+}
+
+impl ShellCommand for Shift {
+    fn execute(&self, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        let count = match self.args.first() {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => {
+                    eprintln!("shift: {}: numeric argument required", arg);
+                    return Ok(1);
+                }
+            },
+        };
+
+        // like bash, shifting past the end fails and changes nothing
+        if count > env.positional.len() {
+            return Ok(1);
+        }
+        env.positional.drain(..count);
+        Ok(0)
+    }
+}
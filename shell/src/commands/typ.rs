@@ -24,7 +24,9 @@ impl ShellCommand for Type {
         match  get_command_type(cmd, _env){
             CommandType::Builtin => println!("{} is a push Builtin\r", cmd),
             CommandType::External(path) =>println!("{} is an external command located at: {}\r", cmd, path),
-            CommandType::Function(func) =>  println!("{} is a function with definition: {}\r", cmd, func),
+            CommandType::Function(func) => {
+                print!("{} is a function\r\n{}", cmd, func.format().replace('\n', "\r\n"))
+            }
             CommandType::Undefined => println!("{} is not a command\r", cmd),
         }
         return  Ok(0);
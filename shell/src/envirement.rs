@@ -5,6 +5,8 @@ use crate::features::jobs::Jobs;
 use crate::parser::types::AstNode;
 
 use dirs::home_dir;
+use nix::unistd::Pid;
+use std::os::fd::RawFd;
 use std::env;
 use std::fs::read_to_string;
 use whoami;
@@ -17,18 +19,53 @@ fn get_user_shell(username: &str) -> Option<String> {
         .and_then(|line| line.split(':').nth(6).map(String::from))
 }
 
+/// Whether `name` can name a variable: a letter or `_`, then letters,
+/// digits and `_`.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The variables a function's `local` hid, with the values to put back.
+pub type LocalFrame = Vec<(String, Option<(String, bool)>)>;
+
 /// Represents the current shell environment.
 #[derive(Clone)]
 
 pub struct ShellEnv {
     pub variables: HashMap<String, (String, bool)>,
     pub arith_vars: HashMap<String, i64>,
+    /// The `FunctionDef` nodes by name
     pub functions: HashMap<String, AstNode>,
     pub jobs: Jobs,
     pub next_job_id: usize,
     pub last_status: i32,
     pub started_at: SystemTime,
     pub current_command: String,
+    /// `$0`
+    pub shell_name: String,
+    /// `$1`, `$2`, ... replaced by `set --` and for each function call
+    pub positional: Vec<String>,
+    /// `$$`, kept by subshells
+    pub shell_pid: u32,
+    /// Single-letter options reported by `$-`
+    pub flags: String,
+    /// `set -o pipefail`, a pipeline fails when any of its stages does
+    pub pipefail: bool,
+    /// The status of each stage of the last pipeline, `PIPESTATUS`
+    pub pipestatus: Vec<i32>,
+    /// The shell's pipe ends and the children of `<(...)` and `>(...)`
+    pub process_substitutions: Vec<(RawFd, Pid)>,
+    /// Children of process substitutions whose command has started, reaped
+    /// once they exit
+    pub substitution_children: Vec<Pid>,
+    /// One frame per running function call, holding the values that its
+    /// `local` variables hide
+    pub local_frames: Vec<LocalFrame>,
+    /// How many loops the running command is in, a function called from
+    /// it can `break` or `continue` them
+    pub loop_depth: usize,
 }
 
 impl ShellEnv {
@@ -43,14 +80,13 @@ impl ShellEnv {
         let username = whoami::username();
         variables.insert("USER".to_string(), (username.clone(), true));
 
-        // HOME and ~
+        // HOME
         let home = home_dir()
             .map(|p| (p.to_string_lossy().into_owned(), true))
             .or_else(|| env::var("HOME").ok().map(|p| (p, true)))
             .unwrap_or_else(|| ("/".to_string(), true));
 
         variables.insert("HOME".to_string(), home.clone());
-        variables.insert("~".to_string(), home.clone());
         // SHELL
         let shell = get_user_shell(&username).unwrap_or_default();
         variables.insert("SHELL".to_string(), (shell, true));
@@ -65,15 +101,13 @@ impl ShellEnv {
             eprintln!("Failed to get current working directory\r");
         }
 
-        // positional arguments
-        let args = env::args();
-        for (i, arg) in args.enumerate() {
-            let key: String = format!("{}", i);
-            variables.insert(key, (arg, true));
-        }
-
         variables.extend(std::env::vars().map(|k| (k.0, (k.1, true))));
 
+        // prompt shown while a command continues on the next line
+        variables
+            .entry("PS2".to_string())
+            .or_insert(("> ".to_string(), false));
+
         return Self {
             variables,
             arith_vars: HashMap::new(),
@@ -83,24 +117,49 @@ impl ShellEnv {
             last_status: 0,
             started_at: SystemTime::now(),
             current_command: String::new(),
+            shell_name: env::args().next().unwrap_or_default(),
+            positional: Vec::new(),
+            shell_pid: std::process::id(),
+            flags: String::new(),
+            pipefail: false,
+            pipestatus: vec![0],
+            process_substitutions: Vec::new(),
+            substitution_children: Vec::new(),
+            local_frames: Vec::new(),
+            loop_depth: 0,
         };
     }
 
     /// Set a shell variable
     pub fn set_local_var(&mut self, key: &str, value: &str) {
+        self.arith_vars.remove(key);
         self.variables
             .insert(key.to_string(), (value.to_string(), false));
     }
 
     pub fn set_env_var(&mut self, key: &str, value: &str) {
+        self.arith_vars.remove(key);
         self.variables
             .insert(key.to_string(), (value.to_string(), true));
     }
 
+    /// Set a variable to the result of an arithmetic evaluation, keeping its export flag
+    pub fn set_arith(&mut self, key: &str, value: i64) {
+        let exported = self.variables.get(key).map(|v| v.1).unwrap_or(false);
+        self.variables
+            .insert(key.to_string(), (value.to_string(), exported));
+        self.arith_vars.insert(key.to_string(), value);
+    }
+
+    /// Get the cached integer value of a variable last set by arithmetic
+    pub fn get_arith(&self, key: &str) -> Option<i64> {
+        self.arith_vars.get(key).copied()
+    }
+
     /// Get a shell variable
     pub fn get(&self, key: &str) -> Option<String> {
-        if key == "?" {
-            return Some(self.last_status.to_string());
+        if let Some(value) = self.special_parameter(key) {
+            Some(value)
         } else if let Some(value) = self.variables.get(key) {
             Some(value.0.clone())
         } else {
@@ -108,6 +167,56 @@ impl ShellEnv {
         }
     }
 
+    /// Whether a variable exists at all, `get` cannot tell unset from empty
+    pub fn is_set(&self, key: &str) -> bool {
+        match key {
+            "!" => self.last_job_pid().is_some(),
+            _ if key.starts_with(|c: char| c.is_ascii_digit()) => {
+                self.special_parameter(key).is_some()
+            }
+            "?" | "#" | "@" | "*" | "$" | "-" | "PIPESTATUS" => true,
+            _ => self.variables.contains_key(key),
+        }
+    }
+
+    /// `$?`, `$#`, `$@`, `$*`, `$$`, `$!`, `$-` and the positional parameters.
+    /// `$@` is joined with spaces here, `"$@"` is split back into fields
+    /// during expansion. `$PIPESTATUS` is its first element.
+    fn special_parameter(&self, key: &str) -> Option<String> {
+        match key {
+            "?" => Some(self.last_status.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" => Some(self.positional.join(" ")),
+            "*" => Some(self.positional.join(&self.ifs_separator())),
+            "$" => Some(self.shell_pid.to_string()),
+            "!" => self.last_job_pid().map(|pid| pid.to_string()),
+            "-" => Some(self.flags.clone()),
+            "PIPESTATUS" => self.pipestatus.first().map(i32::to_string),
+            // all digits, read as a number so `${00}` is `$0` and `${01}` is `$1`
+            _ if key.bytes().all(|b| b.is_ascii_digit()) => match key.parse::<usize>().ok()? {
+                0 => Some(self.shell_name.clone()),
+                index => self.positional.get(index - 1).cloned(),
+            },
+            _ => None,
+        }
+    }
+
+    /// The elements of a list variable, `None` for a plain variable.
+    pub fn list(&self, key: &str) -> Option<Vec<String>> {
+        match key {
+            "PIPESTATUS" => Some(self.pipestatus.iter().map(i32::to_string).collect()),
+            _ => None,
+        }
+    }
+
+    /// What `$*` puts between the parameters: the first character of `IFS`.
+    pub fn ifs_separator(&self) -> String {
+        match self.variables.get("IFS") {
+            Some((ifs, _)) => ifs.chars().next().map(String::from).unwrap_or_default(),
+            None => " ".to_string(),
+        }
+    }
+
     pub fn get_environment_only(&self) -> HashMap<String, String> {
         self.variables
             .iter()
@@ -116,8 +225,9 @@ impl ShellEnv {
             .collect()
     }
 
+    /// `$!`, the last process of the most recent background job
     pub fn last_job_pid(&self) -> Option<i32> {
-        self.jobs.get_current_job().map(|job| job.pgid.as_raw())
+        self.jobs.last_background.map(|pid| pid.as_raw())
     }
 
     /// Set last command exit status ($?)
@@ -139,4 +249,50 @@ impl ShellEnv {
     pub fn get_func(&mut self, name: &str) -> Option<&AstNode> {
         self.functions.get(name)
     }
+
+    /// Makes `key` local to the running function call, its current value is
+    /// restored when the call returns. False when no function is running.
+    pub fn make_local(&mut self, key: &str) -> bool {
+        let Some(frame) = self.local_frames.last_mut() else {
+            return false;
+        };
+        if !frame.iter().any(|(name, _)| name == key) {
+            frame.push((key.to_string(), self.variables.get(key).cloned()));
+        }
+        true
+    }
+
+    /// Puts back the variables hidden by the `local`s of the call that returns.
+    pub fn pop_local_frame(&mut self) {
+        for (key, value) in self.local_frames.pop().unwrap_or_default().into_iter().rev() {
+            self.arith_vars.remove(&key);
+            match value {
+                Some(value) => self.variables.insert(key, value),
+                None => self.variables.remove(&key),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positional_parameters_start_at_one() {
+        let mut env = ShellEnv::new();
+        env.positional = vec!["a".to_string(), "b".to_string()];
+        assert_eq!(env.special_parameter("1").as_deref(), Some("a"));
+        assert_eq!(env.special_parameter("2").as_deref(), Some("b"));
+        assert_eq!(env.special_parameter("3"), None);
+        assert_eq!(env.special_parameter("01").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn zero_with_any_number_of_digits_is_the_shell_name() {
+        let mut env = ShellEnv::new();
+        env.shell_name = "name".to_string();
+        assert_eq!(env.special_parameter("0").as_deref(), Some("name"));
+        assert_eq!(env.special_parameter("00").as_deref(), Some("name"));
+    }
 }
@@ -1,3 +1,4 @@
+use crate::lexer::types::Span;
 use colored::Colorize;
 use std::fmt;
 use std::io;
@@ -11,6 +12,8 @@ pub enum ShellError {
     Parse(String),
     Eval(String),
     Push(String),
+    /// No builtin, function or executable in `PATH` has the name
+    CommandNotFound(String),
     Exec(String),
     Expansion(String),
     UnexpectedEof,
@@ -20,6 +23,10 @@ pub enum ShellError {
     InvalidInput(String),
     Break(usize),
     Continue(usize),
+    /// `return` unwinding to the function call, with the call's status
+    Return(i32),
+    /// A syntax or parse error with the span of the token it points at
+    Located(Box<ShellError>, Span),
 }
 
 impl ShellError {
@@ -29,18 +36,65 @@ impl ShellError {
             ShellError::Io(_) => 1,
             ShellError::Syntax(_) => 2,
             ShellError::Parse(_) => 2,
-            ShellError::Eval(_) => 3,
+            ShellError::Eval(_) => 1,
             ShellError::Exec(_) => 126,
-            ShellError::Expansion(_) => 4,
+            ShellError::CommandNotFound(_) => 127,
+            ShellError::Expansion(_) => 1,
             ShellError::UnexpectedEof => 2,
             ShellError::UnclosedQuote => 2,
-            ShellError::InvalidVariable(_) => 5,
-            ShellError::DivisionByZero => 6,
+            ShellError::InvalidVariable(_) => 1,
+            ShellError::DivisionByZero => 1,
             Self::InvalidInput(_) => 1,
             ShellError::Push(_) => 1,
+            ShellError::Located(error, _) => error.code(),
             _ => 1
         }
     }
+
+    /// Formats the error for the script `name` whose text is `source`. A
+    /// located error names the line, quotes the offending token and shows
+    /// the line with a caret under it, other errors print as usual.
+    pub fn describe(&self, source: &str, name: &str) -> String {
+        let ShellError::Located(error, span) = self else {
+            return self.to_string();
+        };
+        let message = match error.as_ref() {
+            ShellError::Syntax(msg) | ShellError::Parse(msg) => msg,
+            _ => return error.to_string(),
+        };
+        let token = source
+            .get(span.start..span.end)
+            .filter(|text| !text.is_empty() && !text.contains('\n'));
+        let mut message = format!("{}: line {}: {}", name, span.line, message);
+        if let Some(token) = token {
+            message.push_str(&format!(" near `{}`", token));
+        }
+        let header = match error.as_ref() {
+            ShellError::Parse(_) => ShellError::Parse(message),
+            _ => ShellError::Syntax(message),
+        };
+
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line = source[line_start..].lines().next().unwrap_or("");
+        // keep tabs so the caret lines up with the text above it
+        let indent: String = line
+            .chars()
+            .take(span.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = token.map_or(1, |token| token.chars().count());
+        let number = span.line.to_string();
+        format!(
+            "{}\n {} | {}\n {} | {}{}",
+            header,
+            number.blue().bold(),
+            line,
+            " ".repeat(number.len()),
+            indent,
+            "^".repeat(width).red().bold()
+        )
+    }
 }
 
 impl From<io::Error> for ShellError {
@@ -77,6 +131,10 @@ impl fmt::Display for ShellError {
             ShellError::Push(msg) => {
                 write!(f, "{}", format_error("[push]", colored::Color::Magenta, msg))
             },
+            ShellError::CommandNotFound(name) => {
+                let msg = format!("Command not found: {}", name);
+                write!(f, "{}", format_error("[push]", colored::Color::Magenta, &msg))
+            },
             ShellError::Expansion(msg) => {
                 write!(f, "{}", format_error("[Expansion]", colored::Color::Cyan, msg))
             }
@@ -101,6 +159,10 @@ impl fmt::Display for ShellError {
             ShellError::Continue(_) => {
               write!(f, "{}", format_error("[Push]", colored::Color::Yellow, 
                         "continue: only meaningful in a `for', `while', or `until' loop"))},
+            ShellError::Return(_) => {
+              write!(f, "{}", format_error("[Push]", colored::Color::Yellow,
+                        "return: can only `return' from a function"))},
+            ShellError::Located(error, _) => write!(f, "{}", error),
         }
     }
 }
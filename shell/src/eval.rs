@@ -1,7 +1,239 @@
-use crate::parser::types::ArithmeticExpr;
+use crate::parser::parse_arithmetic::{ArithmeticParser, parse_arith_number};
+use crate::parser::types::{ArithmeticExpr, BinaryOperator, UnaryOperator};
 use crate::envirement::ShellEnv;
 use crate::error::ShellError;
+use crate::lexer::tokenize::Tokenizer;
 
-pub fn eval_arith(_: &ArithmeticExpr, _: &mut ShellEnv) -> Result<i64, ShellError> {
-    todo!();
+// Variables may hold expressions that refer to other variables, stop before looping forever
+const MAX_RECURSION: usize = 1024;
+
+pub fn eval_arith(expr: &ArithmeticExpr, env: &mut ShellEnv) -> Result<i64, ShellError> {
+    eval_with_depth(expr, env, 0)
+}
+
+/// Expands `$var`, `$(cmd)` and nested `$((...))` inside the text of an
+/// arithmetic expression, then parses and evaluates it.
+pub fn eval_arith_text(text: &str, env: &mut ShellEnv) -> Result<i64, ShellError> {
+    let expanded = expand_arith_text(text, env)?;
+    eval_str(&expanded, env, 0)
+}
+
+/// Evaluates text that has already been expanded, as the arguments of `let` are.
+pub fn eval_arith_str(text: &str, env: &mut ShellEnv) -> Result<i64, ShellError> {
+    eval_str(text, env, 0)
+}
+
+fn expand_arith_text(text: &str, env: &mut ShellEnv) -> Result<String, ShellError> {
+    if !text.contains(['$', '`', '"']) {
+        return Ok(text.to_string());
+    }
+    Tokenizer::arithmetic_word(text)?.try_expand(env)
+}
+
+fn eval_str(text: &str, env: &mut ShellEnv, depth: usize) -> Result<i64, ShellError> {
+    match ArithmeticParser::new(text)?.parse()? {
+        Some(expr) => eval_with_depth(&expr, env, depth),
+        None => Ok(0),
+    }
+}
+
+fn eval_with_depth(expr: &ArithmeticExpr, env: &mut ShellEnv, depth: usize) -> Result<i64, ShellError> {
+    match expr {
+        ArithmeticExpr::Literal(n) => Ok(*n),
+        ArithmeticExpr::Variable(name) => get_variable(name, env, depth),
+        ArithmeticExpr::UnaryOp { op, expr } => {
+            let value = eval_with_depth(expr, env, depth)?;
+            Ok(match op {
+                UnaryOperator::Plus => value,
+                UnaryOperator::Negate => value.wrapping_neg(),
+                UnaryOperator::Not => (value == 0) as i64,
+                UnaryOperator::BitNot => !value,
+            })
+        }
+        ArithmeticExpr::BinaryOp { op, lhs, rhs } => {
+            // logical operators and the comma short-circuit, so side effects stay correct
+            match op {
+                BinaryOperator::LogicalAnd => {
+                    if eval_with_depth(lhs, env, depth)? == 0 {
+                        return Ok(0);
+                    }
+                    return Ok((eval_with_depth(rhs, env, depth)? != 0) as i64);
+                }
+                BinaryOperator::LogicalOr => {
+                    if eval_with_depth(lhs, env, depth)? != 0 {
+                        return Ok(1);
+                    }
+                    return Ok((eval_with_depth(rhs, env, depth)? != 0) as i64);
+                }
+                BinaryOperator::Comma => {
+                    eval_with_depth(lhs, env, depth)?;
+                    return eval_with_depth(rhs, env, depth);
+                }
+                _ => {}
+            }
+            let left = eval_with_depth(lhs, env, depth)?;
+            let right = eval_with_depth(rhs, env, depth)?;
+            apply_binary(*op, left, right)
+        }
+        ArithmeticExpr::Assignment { var, value } => {
+            let value = eval_with_depth(value, env, depth)?;
+            env.set_arith(var, value);
+            Ok(value)
+        }
+        ArithmeticExpr::CompoundAssignment { var, op, value } => {
+            let current = get_variable(var, env, depth)?;
+            let rhs = eval_with_depth(value, env, depth)?;
+            let result = apply_binary(*op, current, rhs)?;
+            env.set_arith(var, result);
+            Ok(result)
+        }
+        ArithmeticExpr::Ternary {
+            condition,
+            then_expr,
+            else_expr,
+        } => {
+            if eval_with_depth(condition, env, depth)? != 0 {
+                eval_with_depth(then_expr, env, depth)
+            } else {
+                eval_with_depth(else_expr, env, depth)
+            }
+        }
+        ArithmeticExpr::PreIncrement(var) => step_variable(var, 1, true, env, depth),
+        ArithmeticExpr::PreDecrement(var) => step_variable(var, -1, true, env, depth),
+        ArithmeticExpr::PostIncrement(var) => step_variable(var, 1, false, env, depth),
+        ArithmeticExpr::PostDecrement(var) => step_variable(var, -1, false, env, depth),
+    }
+}
+
+fn step_variable(
+    var: &str,
+    delta: i64,
+    prefix: bool,
+    env: &mut ShellEnv,
+    depth: usize,
+) -> Result<i64, ShellError> {
+    let old = get_variable(var, env, depth)?;
+    let new = old.wrapping_add(delta);
+    env.set_arith(var, new);
+    Ok(if prefix { new } else { old })
+}
+
+fn get_variable(name: &str, env: &mut ShellEnv, depth: usize) -> Result<i64, ShellError> {
+    if let Some(value) = env.get_arith(name) {
+        return Ok(value);
+    }
+
+    let text = env.get(name).unwrap_or_default();
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    if let Ok(value) = parse_arith_number(text) {
+        return Ok(value);
+    }
+
+    // The value is itself an expression, e.g. `a=b+1`
+    if depth >= MAX_RECURSION {
+        return Err(ShellError::Eval(format!(
+            "{}: expression recursion level exceeded",
+            name
+        )));
+    }
+    eval_str(text, env, depth + 1)
+}
+
+fn apply_binary(op: BinaryOperator, left: i64, right: i64) -> Result<i64, ShellError> {
+    let value = match op {
+        BinaryOperator::Add => left.wrapping_add(right),
+        BinaryOperator::Sub => left.wrapping_sub(right),
+        BinaryOperator::Mul => left.wrapping_mul(right),
+        BinaryOperator::Div => {
+            if right == 0 {
+                return Err(ShellError::DivisionByZero);
+            }
+            left.wrapping_div(right)
+        }
+        BinaryOperator::Mod => {
+            if right == 0 {
+                return Err(ShellError::DivisionByZero);
+            }
+            left.wrapping_rem(right)
+        }
+        BinaryOperator::Pow => {
+            if right < 0 {
+                return Err(ShellError::Eval("exponent less than 0".to_string()));
+            }
+            let (mut result, mut base, mut exponent) = (1i64, left, right as u64);
+            while exponent > 0 {
+                if exponent & 1 == 1 {
+                    result = result.wrapping_mul(base);
+                }
+                base = base.wrapping_mul(base);
+                exponent >>= 1;
+            }
+            result
+        }
+        BinaryOperator::Eq => (left == right) as i64,
+        BinaryOperator::Neq => (left != right) as i64,
+        BinaryOperator::Lt => (left < right) as i64,
+        BinaryOperator::Gt => (left > right) as i64,
+        BinaryOperator::Le => (left <= right) as i64,
+        BinaryOperator::Ge => (left >= right) as i64,
+        BinaryOperator::BitAnd => left & right,
+        BinaryOperator::BitOr => left | right,
+        BinaryOperator::BitXor => left ^ right,
+        BinaryOperator::ShiftLeft => left.wrapping_shl(right as u32),
+        BinaryOperator::ShiftRight => left.wrapping_shr(right as u32),
+        BinaryOperator::LogicalAnd => (left != 0 && right != 0) as i64,
+        BinaryOperator::LogicalOr => (left != 0 || right != 0) as i64,
+        BinaryOperator::Comma => right,
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, env: &mut ShellEnv) -> Result<i64, ShellError> {
+        eval_arith_text(text, env)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let mut env = ShellEnv::new();
+        assert_eq!(eval("1 + 2 * 3", &mut env).unwrap(), 7);
+        assert_eq!(eval("2 ** 3 ** 2", &mut env).unwrap(), 512);
+        assert_eq!(eval("10 - 4 - 3", &mut env).unwrap(), 3);
+        assert_eq!(eval("0 ? 1 : 2 ? 3 : 4", &mut env).unwrap(), 3);
+    }
+
+    #[test]
+    fn assignment_updates_variables() {
+        let mut env = ShellEnv::new();
+        assert_eq!(eval("x = 5, x += 2, x++", &mut env).unwrap(), 7);
+        assert_eq!(env.get("x").as_deref(), Some("8"));
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let mut env = ShellEnv::new();
+        assert!(eval("1 / 0", &mut env).is_err());
+        assert!(eval("1 % 0", &mut env).is_err());
+    }
+
+    #[test]
+    fn quotes_are_removed_around_operands() {
+        let mut env = ShellEnv::new();
+        env.set_local_var("x", "4");
+        assert_eq!(eval("\"$x\" * 2", &mut env).unwrap(), 8);
+        assert_eq!(eval("3 * \"2\"", &mut env).unwrap(), 6);
+    }
+
+    #[test]
+    fn quotes_inside_substitutions_are_kept() {
+        let mut env = ShellEnv::new();
+        assert_eq!(eval("$(printf \"%s\" \"3\") + 1", &mut env).unwrap(), 4);
+        assert_eq!(eval("$(echo \"a  b\" | wc -c) + 0", &mut env).unwrap(), 5);
+    }
 }
@@ -1,17 +1,18 @@
 use crate::envirement::ShellEnv;
+use crate::error::ShellError;
 use crate::features::history;
 use crate::features::history::History;
 use crate::lexer::tokenize::Tokenizer;
 use crate::parser::*;
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 
+use crate::commands::read::read_line;
 use crate::features::jobs::ProcessStatus;
 use crate::shell_interactions::utils::parse_input;
 use crate::shell_interactions::utils::*;
 use crate::{exec::*, parser};
 
 use std::io::*;
-use std::io::{self, BufRead};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
@@ -25,6 +26,25 @@ pub enum ShellMode {
     Interactive,
     NonInteractive,
     Command(String),
+    // `push file args...`: run the script at this path
+    Script(String),
+    // Look at a script instead of running it: (what to do, source, script name)
+    FrontEnd(FrontEnd, String, String),
+}
+
+/// The debugging flags that stop after tokenizing or parsing.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrontEnd {
+    /// `-n`: report syntax errors only
+    Check,
+    /// `--dump-tokens`: print each token with its line and column
+    DumpTokens,
+    /// `--dump-ast`: print the parsed tree
+    DumpAst,
+    /// `--fmt`: print the script in canonical layout
+    Format,
+    /// `--fmt -w`: rewrite the script file in canonical layout
+    FormatInPlace,
 }
 
 pub enum OutputTarget {
@@ -46,6 +66,8 @@ pub struct Shell {
     pub env: Arc<Mutex<ShellEnv>>,
     pub mode: ShellMode,
     pub cursor_position: CursorPosition,
+    // lines already entered for a command that is not complete yet
+    pub pending_input: String,
 }
 
 impl Shell {
@@ -61,7 +83,15 @@ impl Shell {
         } else {
             OutputTarget::Stdout(stdout())
         };
-        let env = Arc::new(Mutex::new(ShellEnv::new()));
+        let mut shell_env = ShellEnv::new();
+        shell_env.flags = match mode {
+            ShellMode::Interactive => "i",
+            ShellMode::NonInteractive => "s",
+            ShellMode::Command(_) | ShellMode::FrontEnd(..) => "c",
+            ShellMode::Script(_) => "",
+        }
+        .to_string();
+        let env = Arc::new(Mutex::new(shell_env));
         start_reaper(env.clone());
         Self {
             stdin: stdin(),
@@ -76,6 +106,7 @@ impl Shell {
             free_lines: 0,
             mode,
             cursor_position: CursorPosition::new(0, 0),
+            pending_input: String::new(),
         }
     }
 
@@ -197,6 +228,9 @@ impl Shell {
             match new_key {
                 termion::event::Key::Char('\n') => {
                     self.cursor_position.reset();
+                    if self.continue_command() {
+                        continue;
+                    }
                     Shell::parse_and_exec(
                         &mut self.stdout,
                         &mut self.buffer,
@@ -249,80 +283,146 @@ impl Shell {
         }
     }
 
+    /// Stdin is read without buffering so the commands that run get the
+    /// input that follows them (`read`, `cat`, ...).
     pub fn run_non_interactive_stdin(&mut self) {
-        let stdin = io::stdin();
-        for line in stdin.lock().lines() {
-            let line = match line {
-                Ok(val) => val,
-                Err(e) => {
-                    eprintln!("{e}");
-                    std::process::exit(1);
-                }
-            };
-            match Tokenizer::new(&line).tokenize() {
-                Ok(tokens) => match parser::Parser::new(tokens).parse() {
-                    Ok(ast) => match ast {
-                        Some(tree) => {
-                            // Properly lock the mutex before using it
-                            let mut env_guard = self.env.lock().unwrap_or_else(|e| e.into_inner());
-                            match execute(&tree, &mut *env_guard) {
-                                Ok(status) => {
-                                    env_guard.last_status = status;
-                                }
-                                Err(err) => {
-                                    eprintln!("{}", err);
-                                }
-                            }
-                        }
-                        None => return,
-                    },
-                    Err(error) => {
-                        eprintln!("{}", error,)
-                    }
-                },
-                Err(error) => {
-                    eprintln!("{}", error,)
-                }
+        self.run_lines(|| read_line(0).map(|(line, _)| line));
+    }
+
+    /// Gathers lines until they make a complete command and runs it before
+    /// reading any further, so a script can change what the rest of it does.
+    /// A syntax error stops the script, nothing after it runs.
+    fn run_lines(&mut self, mut next_line: impl FnMut() -> Option<String>) {
+        let mut pending = String::new();
+        let mut lines_read = 0;
+        let mut first_line = 1;
+        while let Some(line) = next_line() {
+            lines_read += 1;
+            pending.push_str(&line);
+            if needs_more_input(&pending) {
+                pending.push('\n');
+                continue;
+            }
+            let command = std::mem::take(&mut pending);
+            if !self.run_source(&command, first_line) {
+                return;
             }
+            first_line = lines_read + 1;
+        }
+        // input ended in the middle of a command, report it
+        if !pending.is_empty() {
+            self.run_source(&pending, first_line);
         }
     }
 
     pub fn handle_command(&mut self, cmd: &str) {
-        match Tokenizer::new(cmd).tokenize() {
-            Ok(tokens) => match Parser::new(tokens).parse() {
-                Ok(ast) => match ast {
-                    Some(tree) => {
-                        // Properly lock the mutex before using it
-                        let mut env_guard = self.env.lock().unwrap_or_else(|e| e.into_inner());
-                        match execute(&tree, &mut *env_guard) {
-                            Ok(status) => {
-                                env_guard.last_status = status;
-                            }
-                            Err(err) => {
-                                eprintln!("{}", err);
-                            }
-                        }
+        self.run_source(cmd, 1);
+    }
+
+    /// Parses and runs `cmd`, which starts on line `first_line` of the script.
+    /// Returns false when it does not parse.
+    fn run_source(&mut self, cmd: &str, first_line: usize) -> bool {
+        // Properly lock the mutex before using it
+        let mut env_guard = self.env.lock().unwrap_or_else(|e| e.into_inner());
+        match parse_source(cmd) {
+            Ok(ast) => match ast {
+                Some(tree) => match execute(&tree, &mut *env_guard) {
+                    Ok(status) => {
+                        env_guard.last_status = status;
                     }
-                    None => {
-                        return;
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        env_guard.last_status = err.code();
                     }
                 },
-                Err(error) => {
-                    eprintln!("{}", error,)
-                }
+                None => {}
             },
-            Err(error) => {
-                eprintln!("{}", error,)
+            Err(mut error) => {
+                if let ShellError::Located(_, span) = &mut error {
+                    span.line += first_line - 1;
+                }
+                eprintln!("{}", error.describe(cmd, &env_guard.shell_name));
+                env_guard.last_status = error.code();
+                return false;
             }
         };
+        true
     }
 
-    pub fn run(&mut self) {
+    /// Tokenizes or parses `source` without running it. A syntax error is
+    /// reported against `name` and exits with its status.
+    pub fn run_front_end(&mut self, action: FrontEnd, source: &str, name: &str) {
+        let fail = |error: ShellError| -> ! {
+            eprintln!("{}", error.describe(source, name));
+            std::process::exit(error.code());
+        };
+        if action == FrontEnd::DumpTokens {
+            let mut tokenizer = Tokenizer::new(source);
+            let tokens = tokenizer.tokenize().unwrap_or_else(|e| fail(e));
+            for (token, span) in tokens.iter().zip(&tokenizer.spans) {
+                println!("{}:{}\t{:?}", span.line, span.column, token);
+            }
+            return;
+        }
+
+        let ast = if action == FrontEnd::DumpAst {
+            parse_source(source)
+        } else {
+            parse_source_with_comments(source)
+        };
+        let ast = ast.unwrap_or_else(|e| fail(e));
+        match (action, ast) {
+            (FrontEnd::DumpAst, Some(tree)) => print!("{}", tree),
+            (FrontEnd::Format | FrontEnd::FormatInPlace, tree) => {
+                let formatted = tree.map(|tree| tree.format()).unwrap_or_default();
+                if action == FrontEnd::Format {
+                    print!("{}", formatted);
+                } else if let Err(e) = std::fs::write(name, formatted) {
+                    eprintln!("{}: {}", name, e);
+                    std::process::exit(1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets `$0` and the positional parameters `$1`, `$2`, ...
+    pub fn set_arguments(&mut self, name: Option<String>, params: Vec<String>) {
+        let mut env_guard = self.env.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(name) = name {
+            env_guard.shell_name = name;
+        }
+        env_guard.positional = params;
+    }
+
+    pub fn run_script(&mut self, path: &str) {
+        match std::fs::read_to_string(path) {
+            Ok(source) => {
+                let mut lines = source.lines();
+                self.run_lines(|| lines.next().map(str::to_string));
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                let status = if e.kind() == ErrorKind::NotFound { 127 } else { 126 };
+                self.env.lock().unwrap_or_else(|e| e.into_inner()).last_status = status;
+            }
+        }
+    }
+
+    /// Runs the shell in its mode and returns the status it should exit with,
+    /// the one of the last command run.
+    pub fn run(&mut self) -> i32 {
         match &self.mode {
             ShellMode::Interactive => self.run_interactive_shell(),
             ShellMode::NonInteractive => self.run_non_interactive_stdin(),
             ShellMode::Command(cmd) => self.handle_command(cmd.clone().as_str()),
+            ShellMode::Script(path) => self.run_script(path.clone().as_str()),
+            ShellMode::FrontEnd(action, source, name) => {
+                let (action, source, name) = (*action, source.clone(), name.clone());
+                self.run_front_end(action, &source, &name)
+            }
         }
+        self.env.lock().unwrap_or_else(|e| e.into_inner()).last_status
     }
 }
 
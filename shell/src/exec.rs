@@ -11,15 +11,26 @@ use crate::commands::exit::Exit;
 use crate::commands::fg::Fg;
 use crate::commands::jobs::Jobs;
 use crate::commands::kill::Kill;
+use crate::commands::lett::Let;
+use crate::commands::local::Local;
+use crate::commands::read::Read;
+use crate::commands::set::Set;
+use crate::commands::shift::Shift;
+use crate::eval::eval_arith_text;
 use crate::executorr::spawn_commande::spawn_command;
-use nix::sys::signal::{Signal, signal};
+use crate::expansion::{expand_and_split, expand_pattern, reap_process_substitutions};
+use crate::pattern::pattern_matches;
+use crate::redirection::{apply_redirects, restore_shell_fds};
+use nix::sys::signal::{SigHandler, Signal, signal};
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
-use nix::unistd::pipe;
+use nix::fcntl::OFlag;
+use nix::unistd::pipe2;
 use nix::unistd::setpgid;
-use nix::unistd::{getpgrp, tcsetpgrp};
+use nix::unistd::{ForkResult, close, dup2, fork, getpgrp, tcgetpgrp, tcsetpgrp};
 use std::collections::HashMap;
 use std::fs::File;
+use std::io::Write;
 use std::os::fd::IntoRawFd;
 use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
 
@@ -47,7 +58,32 @@ pub fn execute_with_background(
     is_background: bool,
     loop_depth: usize,
 ) -> Result<i32, ShellError> {
-    env.current_command = ast.to_text(env);
+    // a compound command's redirections stay in place while it runs and
+    // are undone whatever it returns
+    let saved = apply_redirects(ast.compound_redirects(), env)?;
+    env.loop_depth = loop_depth;
+    let result = run_node(ast, env, is_background, loop_depth);
+    restore_shell_fds(saved);
+    record_pipestatus(ast, &result, env);
+    reap_process_substitutions(env);
+    result
+}
+
+/// Sets `PIPESTATUS` after a simple command or subshell, which are
+/// pipelines of one stage. A longer pipeline sets it when waited for.
+pub fn record_pipestatus(ast: &AstNode, result: &Result<i32, ShellError>, env: &mut ShellEnv) {
+    if let (AstNode::Command { .. } | AstNode::Subshell { .. }, Ok(status)) = (ast, result) {
+        env.pipestatus = vec![*status];
+    }
+}
+
+fn run_node(
+    ast: &AstNode,
+    env: &mut ShellEnv,
+    is_background: bool,
+    loop_depth: usize,
+) -> Result<i32, ShellError> {
+    env.current_command = ast.to_text();
     match ast {
         AstNode::Command {
             cmd,
@@ -80,7 +116,7 @@ pub fn execute_with_background(
                             jobs::JobStatus::Running,
                             cmd.expand(env) + " " + &merged.expand(env),
                         );
-                        env.jobs.add_job(new_job.clone());
+                        env.jobs.add_background_job(new_job.clone());
                         new_job.status.printStatus(new_job.clone());
                         Ok(0)
                     }
@@ -88,156 +124,7 @@ pub fn execute_with_background(
                 CommandResult::Builtin(n) => Ok(n),
             }
         }
-        AstNode::Pipeline(nodes) => {
-            if nodes.is_empty() {
-                return Ok(0);
-            }
-
-            if nodes.len() == 1 {
-                return execute_with_background(&nodes[0], env, is_background, loop_depth);
-            }
-
-            let mut prev_read: Option<OwnedFd> = None;
-            let mut pipeline_gid = Option::<Pid>::None; // This will store the pipeline's process group ID
-            let mut child_pids = Vec::<Pid>::new();
-
-            // Execute all commands in the pipeline concurrently
-            for (i, node) in nodes.iter().enumerate() {
-                let is_last = i == nodes.len() - 1;
-                let is_first = i == 0;
-
-                if let AstNode::Command {
-                    cmd,
-                    args,
-                    assignments,
-                    redirects,
-                } = node
-                {
-                    let stdin = prev_read.take();
-
-                    // Create new pipe only if this is not the last command
-                    let (read_end, write_end) = if !is_last {
-                        let (read_fd, write_fd) = pipe().expect("pipe failed");
-                        (
-                            Some(unsafe { OwnedFd::from_raw_fd(read_fd.as_raw_fd()) }),
-                            Some(unsafe { OwnedFd::from_raw_fd(write_fd.as_raw_fd()) }),
-                        )
-                    } else {
-                        (None, None)
-                    };
-
-                    let fds_map = {
-                        let mut map: HashMap<u64, OwnedFd> = HashMap::new();
-                        if let Some(stdi) = stdin {
-                            map.insert(0, stdi);
-                        }
-                        if let Some(stdo) = write_end {
-                            map.insert(1, stdo);
-                        }
-                        Some(map)
-                    };
-
-                    // For the first command, we need to create a new process group
-                    // For subsequent commands, we add them to the existing group
-                    let mut current_gid = if is_first {
-                        None // Let spawn_command create a new group
-                    } else {
-                        pipeline_gid // Use the established group
-                    };
-
-                    // Spawn the command without waiting
-                    match spawn_command(
-                        cmd,
-                        args,
-                        assignments,
-                        redirects,
-                        env,
-                        fds_map.as_ref(),
-                        &mut current_gid,
-                    )? {
-                        CommandResult::Child(child_pid) => {
-                            child_pids.push(child_pid);
-
-                            // If this is the first command, its PID becomes the process group ID
-                            if is_first {
-                                pipeline_gid = Some(child_pid);
-
-                                // Make sure the first process becomes the group leader
-                                // This should be done in spawn_command, but ensure it here
-                                if let Err(e) = setpgid(child_pid, child_pid) {
-                                    eprintln!("Warning: Failed to set process group leader: {}", e);
-                                }
-                            } else {
-                                // Add subsequent processes to the pipeline's process group
-                                if let Some(pgid) = pipeline_gid {
-                                    if let Err(e) = setpgid(child_pid, pgid) {
-                                        eprintln!("Warning: Failed to add process to group: {}", e);
-                                    }
-                                }
-                            }
-                        }
-                        CommandResult::Builtin(n) => {
-                            // Builtin commands are executed immediately
-                            // In a real pipeline, builtins should also fork, but this is a simplification
-                        }
-                    }
-
-                    prev_read = read_end; // becomes stdin for next command
-                } else {
-                    return Err(ShellError::Exec(
-                        "Pipeline can only contain commands".to_string(),
-                    ));
-                }
-            }
-
-            // Now handle waiting based on whether it's background or not
-            if !child_pids.is_empty() {
-                if let Some(pgid) = pipeline_gid {
-                    let pipeline_cmd = nodes
-                        .iter()
-                        .filter_map(|node| {
-                            if let AstNode::Command { cmd, .. } = node {
-                                Some(cmd.expand(env))
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                        .join(" | ");
-
-                    if is_background {
-                        // Create job and add all processes to it
-                        let mut new_job = jobs::Job::new(
-                            pgid,
-                            pgid, // leader_pid is same as pgid for pipelines
-                            env.jobs.size + 1,
-                            jobs::JobStatus::Running,
-                            pipeline_cmd,
-                        );
-
-                        // Add all child processes to the job
-                        for (i, &pid) in child_pids.iter().enumerate() {
-                            let cmd_name = if let AstNode::Command { cmd, .. } = &nodes[i] {
-                                cmd.expand(env)
-                            } else {
-                                "unknown".to_string()
-                            };
-                            new_job.add_process(pid, cmd_name);
-                        }
-
-                        env.jobs.add_job(new_job);
-                        return Ok(0);
-                    } else {
-                        // For foreground pipelines, we don't add to jobs but still wait properly
-                        let status = wait_for_pipeline(pgid, child_pids, pipeline_cmd, env)?;
-                        env.set_last_status(status);
-                        return Ok(status);
-                    }
-                }
-            }
-
-            Ok(0)
-        }
+        AstNode::Pipeline(nodes) => run_pipeline(nodes, env, is_background, loop_depth),
 
         AstNode::Background(node) => execute_with_background(node, env, true, loop_depth),
 
@@ -281,16 +168,9 @@ pub fn execute_with_background(
             Ok(inverted_status)
         }
 
-        AstNode::Subshell(node) => {
-            let status = execute_with_background(node, env, is_background, loop_depth)?;
-            env.set_last_status(status);
-            Ok(status)
-        }
+        AstNode::Subshell { body, .. } => run_subshell(body, env, is_background),
 
-        AstNode::Group {
-            commands,
-            redirects: _,
-        } => {
+        AstNode::Group { commands, .. } => {
             let mut last_status = 0;
             for command in commands {
                 last_status = execute_with_background(command, env, is_background, loop_depth)?;
@@ -304,6 +184,7 @@ pub fn execute_with_background(
             then_branch,
             elif,
             else_branch,
+            ..
         } => {
             let condition_status =
                 execute_with_background(condition, env, is_background, loop_depth)?;
@@ -340,12 +221,19 @@ pub fn execute_with_background(
             }
         }
 
-        AstNode::For { var, values, body } => {
+        AstNode::For {
+            var, values, body, ..
+        } => {
             let mut last_status = 0;
             let new_depth = loop_depth + 1; // entering a loop
 
+            let mut items = Vec::new();
             for v in values {
-                env.set_local_var(&var, &v.expand(env));
+                items.extend(expand_and_split(v, env)?);
+            }
+
+            for value in &items {
+                env.set_local_var(var, value);
 
                 match execute_with_background(body, env, is_background, new_depth) {
                     Err(ShellError::Break(mut remaining)) => {
@@ -373,7 +261,9 @@ pub fn execute_with_background(
             Ok(last_status)
         }
 
-        AstNode::While { condition, body } => {
+        AstNode::While {
+            condition, body, ..
+        } => {
             let mut last_status = 0;
             let new_depth = loop_depth + 1; // entering a loop
 
@@ -410,7 +300,9 @@ pub fn execute_with_background(
             Ok(last_status)
         }
 
-        AstNode::Until { condition, body } => {
+        AstNode::Until {
+            condition, body, ..
+        } => {
             let mut last_status = 0;
             let new_depth = loop_depth + 1;
 
@@ -447,6 +339,36 @@ pub fn execute_with_background(
             Ok(last_status)
         }
 
+        AstNode::Case { word, arms, .. } => {
+            let subject = word.try_expand(env)?;
+            let mut status = 0;
+
+            'arms: for (patterns, body) in arms {
+                for pattern in patterns {
+                    if pattern_matches(&expand_pattern(pattern, env)?, &subject) {
+                        status = execute_with_background(body, env, is_background, loop_depth)?;
+                        break 'arms;
+                    }
+                }
+            }
+
+            env.set_last_status(status);
+            Ok(status)
+        }
+
+        AstNode::ArithmeticCommand(expr) => {
+            // An arithmetic error fails the command but does not abort the script
+            let status = match eval_arith_text(expr, env) {
+                Ok(value) => (value == 0) as i32,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+            env.set_last_status(status);
+            Ok(status)
+        }
+
         AstNode::Break(level_word) => {
             let n = parse_level(level_word, env, "break")?;
             let n = n.min(loop_depth);
@@ -459,8 +381,213 @@ pub fn execute_with_background(
             Err(ShellError::Continue(n))
         }
 
-        _ => Ok(0),
+        AstNode::Return(status_word) => leave_function(status_word, env),
+
+        AstNode::FunctionDef { name, .. } => {
+            env.set_func(name.expand(env), ast.clone());
+            env.set_last_status(0);
+            Ok(0)
+        }
+
+        AstNode::Comment { .. } => Ok(env.get_last_status()),
+    }
+}
+
+/// Runs the body of `( ... )` in a forked copy of the shell, so what it does
+/// to variables, functions or the current directory stays there. The child
+/// leads its own process group, waited for like an external command.
+pub fn run_subshell(
+    node: &AstNode,
+    env: &mut ShellEnv,
+    is_background: bool,
+) -> Result<i32, ShellError> {
+    let child = fork_shell(env, |env| {
+        let _ = setpgid(Pid::from_raw(0), Pid::from_raw(0));
+        execute(node, env)
+    })?;
+    let _ = setpgid(child, child);
+    let name = format!("( {} )", node.to_text());
+    if is_background {
+        let new_job = jobs::Job::new(
+            child,
+            child,
+            env.jobs.size + 1,
+            jobs::JobStatus::Running,
+            name,
+        );
+        env.jobs.add_background_job(new_job.clone());
+        new_job.status.printStatus(new_job.clone());
+        return Ok(0);
+    }
+    let status = wait_for_single_process(child, env, name)?;
+    env.set_last_status(status);
+    Ok(status)
+}
+
+/// Forks a copy of the shell that calls `run` and exits with its status,
+/// giving the child's pid back to the shell. Output buffered beforehand is
+/// flushed so the child does not write it a second time, and the child
+/// takes the default action for the signals the shell itself ignores.
+pub fn fork_shell(
+    env: &mut ShellEnv,
+    run: impl FnOnce(&mut ShellEnv) -> Result<i32, ShellError>,
+) -> Result<Pid, ShellError> {
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            unsafe {
+                signal(Signal::SIGINT, SigHandler::SigDfl).ok();
+                signal(Signal::SIGQUIT, SigHandler::SigDfl).ok();
+            }
+            exit_subshell(run(env));
+        }
+        Ok(ForkResult::Parent { child }) => Ok(child),
+        Err(e) => Err(ShellError::Exec(format!("Fork failed: {}", e))),
+    }
+}
+
+/// Ends a forked copy of the shell with the status of what it ran.
+fn exit_subshell(result: Result<i32, ShellError>) -> ! {
+    let status = match result {
+        Ok(status) | Err(ShellError::Return(status)) => status,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.code()
+        }
+    };
+    std::io::stdout().flush().ok();
+    std::process::exit(status);
+}
+
+/// Starts every stage of a pipeline in one process group and waits for them
+/// unless it runs in the background. An external command is started
+/// directly, any other stage (a builtin, a function or a compound command)
+/// runs in a forked copy of the shell, so none of them changes this one.
+pub fn run_pipeline(
+    nodes: &[AstNode],
+    env: &mut ShellEnv,
+    is_background: bool,
+    loop_depth: usize,
+) -> Result<i32, ShellError> {
+    if nodes.len() == 1 {
+        return execute_with_background(&nodes[0], env, is_background, loop_depth);
+    }
+
+    let mut prev_read: Option<OwnedFd> = None;
+    let mut pipeline_gid: Option<Pid> = None;
+    let mut child_pids = Vec::<Pid>::new();
+
+    for (i, node) in nodes.iter().enumerate() {
+        let (read_end, write_end) = if i + 1 < nodes.len() {
+            // a command started later must not keep a copy of either end,
+            // the reader would never see the end of its input
+            let (read_fd, write_fd) = pipe2(OFlag::O_CLOEXEC)
+                .map_err(|e| ShellError::Exec(format!("pipe failed: {}", e)))?;
+            unsafe {
+                (
+                    Some(OwnedFd::from_raw_fd(read_fd)),
+                    Some(OwnedFd::from_raw_fd(write_fd)),
+                )
+            }
+        } else {
+            (None, None)
+        };
+
+        let mut fds_map: HashMap<u64, OwnedFd> = HashMap::new();
+        if let Some(stdin) = prev_read.take() {
+            fds_map.insert(0, stdin);
+        }
+        if let Some(stdout) = write_end {
+            fds_map.insert(1, stdout);
+        }
+
+        let child = match node {
+            AstNode::Command {
+                cmd,
+                args,
+                assignments,
+                redirects,
+            } if !cmd.parts.is_empty()
+                && matches!(
+                    get_command_type(&cmd.expand(env), env),
+                    CommandType::External(_)
+                ) =>
+            {
+                let mut gid = pipeline_gid;
+                match spawn_command(cmd, args, assignments, redirects, env, Some(&fds_map), &mut gid)? {
+                    CommandResult::Child(pid) => Some(pid),
+                    CommandResult::Builtin(_) => None,
+                }
+            }
+            _ => Some(fork_stage(node, env, &fds_map, read_end.as_ref(), pipeline_gid)?),
+        };
+
+        if let Some(child) = child {
+            let pgid = *pipeline_gid.get_or_insert(child);
+            let _ = setpgid(child, pgid);
+            child_pids.push(child);
+        }
+        prev_read = read_end; // becomes stdin for next command
+    }
+
+    let Some(pgid) = pipeline_gid else {
+        return Ok(0);
+    };
+    let pipeline_cmd = nodes
+        .iter()
+        .map(AstNode::to_text)
+        .collect::<Vec<_>>()
+        .join(" | ");
+
+    if is_background {
+        let mut new_job = jobs::Job::new(
+            pgid,
+            pgid, // leader_pid is same as pgid for pipelines
+            env.jobs.size + 1,
+            jobs::JobStatus::Running,
+            pipeline_cmd,
+        );
+        for (&pid, node) in child_pids.iter().zip(nodes) {
+            new_job.add_process(pid, node.to_text());
+        }
+        env.jobs.add_background_job(new_job);
+        env.pipestatus = vec![0];
+        return Ok(0);
     }
+
+    let status = wait_for_pipeline(pgid, child_pids, pipeline_cmd, env)?;
+    env.set_last_status(status);
+    Ok(status)
+}
+
+/// Forks a copy of the shell to run one stage of a pipeline with `fds` as
+/// its standard input and output. `next_read` is the read end kept for the
+/// next stage, closed in the child so it alone does not hold the pipe open.
+fn fork_stage(
+    node: &AstNode,
+    env: &mut ShellEnv,
+    fds: &HashMap<u64, OwnedFd>,
+    next_read: Option<&OwnedFd>,
+    pgid: Option<Pid>,
+) -> Result<Pid, ShellError> {
+    fork_shell(env, |env| {
+        let _ = setpgid(Pid::from_raw(0), pgid.unwrap_or(Pid::from_raw(0)));
+        // a stage whose reader is gone just ends
+        unsafe {
+            signal(Signal::SIGPIPE, SigHandler::SigDfl).ok();
+        }
+        if let Some(fd) = next_read {
+            let _ = close(fd.as_raw_fd());
+        }
+        for (&fd, owned_fd) in fds {
+            if dup2(owned_fd.as_raw_fd(), fd as i32).is_err() {
+                return Ok(1);
+            }
+        }
+        execute(node, env)
+    })
 }
 
 pub fn wait_for_single_process(
@@ -482,11 +609,16 @@ pub fn wait_for_single_process(
     let tty = File::open("/dev/tty").map_err(|e| ShellError::Io(e))?;
     let fd = tty.into_raw_fd();
     let shell_pgid = getpgrp();
+    // a shell that is not in the foreground, like the child of `( ... ) &`,
+    // leaves the terminal alone
+    let foreground = tcgetpgrp(fd) == Ok(shell_pgid);
 
     let old = unsafe { signal(Signal::SIGTTOU, nix::sys::signal::SigHandler::SigIgn) }
         .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?;
 
-    tcsetpgrp(fd, pid).map_err(|e| ShellError::Exec(format!("tcsetpgrp error: {}", e)))?;
+    if foreground {
+        tcsetpgrp(fd, pid).map_err(|e| ShellError::Exec(format!("tcsetpgrp error: {}", e)))?;
+    }
 
     unsafe {
         signal(Signal::SIGTTOU, old)
@@ -518,7 +650,9 @@ pub fn wait_for_single_process(
     let old = unsafe { signal(Signal::SIGTTOU, nix::sys::signal::SigHandler::SigIgn) }
         .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?;
 
-    tcsetpgrp(fd, shell_pgid).ok();
+    if foreground {
+        tcsetpgrp(fd, shell_pgid).ok();
+    }
     unsafe {
         signal(Signal::SIGTTOU, old)
             .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?
@@ -547,20 +681,31 @@ pub fn wait_for_pipeline(
     let tty = File::open("/dev/tty").map_err(|e| ShellError::Io(e))?;
     let fd = tty.into_raw_fd();
     let shell_pgid = getpgrp();
+    // as in `wait_for_single_process`
+    let foreground = tcgetpgrp(fd) == Ok(shell_pgid);
 
     let old = unsafe { signal(Signal::SIGTTOU, nix::sys::signal::SigHandler::SigIgn) }
         .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?;
 
-    tcsetpgrp(fd, pgid).map_err(|e| ShellError::Exec(format!("tcsetpgrp error: {}", e)))?;
+    if foreground {
+        tcsetpgrp(fd, pgid).map_err(|e| ShellError::Exec(format!("tcsetpgrp error: {}", e)))?;
+    }
 
     unsafe {
         signal(Signal::SIGTTOU, old)
             .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?
     };
 
-    // Wait for all processes in the pipeline to complete
+    // Wait for all processes in the pipeline to complete, keeping the
+    // status of each stage in order
     let mut remaining_processes = child_pids.len();
-    let mut pipeline_status = 0;
+    let mut statuses = vec![0; child_pids.len()];
+    let mut stopped = false;
+    let mut stage_done = |pid: Pid, code: i32| {
+        if let Some(i) = child_pids.iter().position(|&child| child == pid) {
+            statuses[i] = code;
+        }
+    };
 
     while remaining_processes > 0 {
         match waitpid(
@@ -570,32 +715,37 @@ pub fn wait_for_pipeline(
             Ok(wait_status) => match wait_status {
                 WaitStatus::Exited(pid, code) => {
                     remaining_processes -= 1;
-                    // Use exit code from the last command in pipeline
-                    if child_pids.last() == Some(&pid) {
-                        pipeline_status = code;
-                    }
+                    stage_done(pid, code);
                 }
-                WaitStatus::Signaled(pid, _, _) => {
+                WaitStatus::Signaled(pid, signal, _) => {
                     remaining_processes -= 1;
-                    if child_pids.last() == Some(&pid) {
-                        pipeline_status = 1;
-                    }
+                    stage_done(pid, 128 + signal as i32);
                 }
                 WaitStatus::Stopped(_, _) => {
                     env.jobs.update_job_status(pgid, JobStatus::Stopped);
                     println!();
-                    pipeline_status = 1;
+                    stopped = true;
                     break;
                 }
                 _ => {}
             },
             Err(_) => {
                 remaining_processes = 0;
-                pipeline_status = 1;
+                stopped = true;
             }
         }
     }
 
+    // with pipefail the rightmost stage that failed gives the status
+    let pipeline_status = if stopped {
+        1
+    } else if env.pipefail {
+        statuses.iter().rev().copied().find(|&status| status != 0).unwrap_or(0)
+    } else {
+        statuses.last().copied().unwrap_or(0)
+    };
+    env.pipestatus = statuses;
+
     if remaining_processes == 0 {
         env.jobs.remove_job(pgid);
     }
@@ -604,7 +754,9 @@ pub fn wait_for_pipeline(
     let old = unsafe { signal(Signal::SIGTTOU, nix::sys::signal::SigHandler::SigIgn) }
         .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?;
 
-    tcsetpgrp(fd, shell_pgid).ok();
+    if foreground {
+        tcsetpgrp(fd, shell_pgid).ok();
+    }
     unsafe {
         signal(Signal::SIGTTOU, old)
             .map_err(|e| ShellError::Exec(format!("Signal error: {}", e)))?
@@ -639,6 +791,11 @@ pub fn build_command(
         "[" => Some(Box::new(Test::new(args, true))),
         "true" => Some(Box::new(True::new(args))),
         "false" => Some(Box::new(False::new(args))),
+        "let" => Some(Box::new(Let::new(args))),
+        "set" => Some(Box::new(Set::new(args))),
+        "shift" => Some(Box::new(Shift::new(args))),
+        "read" => Some(Box::new(Read::new(args))),
+        "local" => Some(Box::new(Local::new(args))),
         _ => None,
     }
 }
@@ -657,7 +814,8 @@ pub fn get_command_type(cmd: &str, env: &mut ShellEnv) -> CommandType {
 
     match cmd {
         "echo" | "cd" | "pwd" | "cp" | "rm" | "mv" | "mkdir" | "export" | "exit" | "type"
-        | "fg" | "jobs" | "kill" | "bg" | "test" | "[" | "true" | "false" => CommandType::Builtin,
+        | "fg" | "jobs" | "kill" | "bg" | "test" | "[" | "true" | "false" | "let"
+        | "set" | "shift" | "read" | "local" => CommandType::Builtin,
         _ => match env.get("PATH") {
             Some(bin_path) => {
                 let paths: Vec<&str> = bin_path.split(':').collect();
@@ -691,3 +849,67 @@ fn parse_level(word: &Option<Word>, env: &ShellEnv, cmd: &str) -> Result<usize,
         }
     }
 }
+
+/// The status `return` gives back, `$?` when it has no argument.
+/// Runs `return`. Outside a function it is an error that only fails the
+/// command itself, the rest of the list still runs.
+pub fn leave_function(word: &Option<Word>, env: &mut ShellEnv) -> Result<i32, ShellError> {
+    let status = return_status(word, env)?;
+    if !env.local_frames.is_empty() {
+        return Err(ShellError::Return(status));
+    }
+    eprintln!("{}", ShellError::Return(status));
+    env.set_last_status(2);
+    Ok(2)
+}
+
+pub fn return_status(word: &Option<Word>, env: &ShellEnv) -> Result<i32, ShellError> {
+    let status_str = match word {
+        Some(w) => w.expand(env),
+        None => return Ok(env.get_last_status()),
+    };
+
+    match status_str.parse::<i32>() {
+        Ok(n) => Ok(n & 0xff),
+        Err(_) => Err(ShellError::Push(format!(
+            "return: {}: numeric argument required",
+            status_str
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    fn run(source: &str, env: &mut ShellEnv) -> Result<i32, ShellError> {
+        parse_source(source).unwrap().map_or(Ok(0), |ast| execute(&ast, env))
+    }
+
+    #[test]
+    fn return_outside_a_function_fails_only_itself() {
+        let mut env = ShellEnv::new();
+        assert_eq!(run("return 3", &mut env).unwrap(), 2);
+        run("return 3; a=next", &mut env).unwrap();
+        assert_eq!(env.get("a").as_deref(), Some("next"));
+    }
+
+    #[test]
+    fn prefix_assignments_last_for_the_function_call() {
+        let mut env = ShellEnv::new();
+        run("x=0; f() { seen=$x; }; x=1 y=2 f", &mut env).unwrap();
+        assert_eq!(env.get("seen").as_deref(), Some("1"));
+        assert_eq!(env.get("x").as_deref(), Some("0"));
+        assert!(!env.variables.contains_key("y"));
+    }
+
+    #[test]
+    fn break_and_continue_in_a_function_reach_the_callers_loop() {
+        let mut env = ShellEnv::new();
+        run("f() { break; }; for i in 1 2 3; do n=$i; f; n=no; done", &mut env).unwrap();
+        assert_eq!(env.get("n").as_deref(), Some("1"));
+        run("g() { continue; }; m=none; for i in 1 2; do g; m=$i; done", &mut env).unwrap();
+        assert_eq!(env.get("m").as_deref(), Some("none"));
+    }
+}
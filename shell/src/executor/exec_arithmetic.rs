@@ -0,0 +1,19 @@
+use crate::{error::ShellError, eval::eval_arith_text, executor::Executor, types::AstNode};
+
+impl<'a> Executor<'a> {
+    pub fn exec_arithmetic(&mut self, node: &AstNode) -> Result<i32, ShellError> {
+        if let AstNode::ArithmeticCommand(expr) = node {
+            // An arithmetic error fails the command but does not abort the script
+            let status = match eval_arith_text(expr, self.env) {
+                Ok(value) => (value == 0) as i32,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            };
+            self.env.set_last_status(status);
+            return Ok(status);
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{
+    error::ShellError, executor::Executor, expansion::expand_pattern,
+    pattern::pattern_matches, types::AstNode,
+};
+
+impl<'a> Executor<'a> {
+    pub fn exec_case(
+        &mut self,
+        node: &AstNode,
+        is_background: bool,
+        loop_depth: usize,
+    ) -> Result<i32, ShellError> {
+        if let AstNode::Case { word, arms, .. } = node {
+            let subject = word.try_expand(self.env)?;
+            let mut status = 0;
+
+            'arms: for (patterns, body) in arms {
+                for pattern in patterns {
+                    if pattern_matches(&expand_pattern(pattern, self.env)?, &subject) {
+                        status = self.execute_node(body, is_background, loop_depth)?;
+                        break 'arms;
+                    }
+                }
+            }
+
+            self.env.set_last_status(status);
+            return Ok(status);
+        }
+        unreachable!()
+    }
+}
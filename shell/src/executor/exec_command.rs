@@ -41,7 +41,7 @@ impl<'a> Executor<'a> {
                             JobStatus::Running,
                             cmd.expand(self.env) + " " + &merged.expand(self.env),
                         );
-                        self.env.jobs.add_job(new_job.clone());
+                        self.env.jobs.add_background_job(new_job.clone());
                         new_job.status.printStatus(new_job.clone());
                         return Ok(0);
                     }
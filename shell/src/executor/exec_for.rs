@@ -1,4 +1,4 @@
-use crate::{error::ShellError, executor::Executor, types::AstNode};
+use crate::{error::ShellError, executor::Executor, expansion::expand_and_split, types::AstNode};
 
 impl<'a> Executor<'a> {
     pub fn exec_for(
@@ -7,12 +7,17 @@ impl<'a> Executor<'a> {
         is_background: bool,
         loop_depth: usize,
     ) -> Result<i32, ShellError> {
-        if let AstNode::For { var, values, body } = node {
+        if let AstNode::For { var, values, body, .. } = node {
             let mut last_status = 0;
             let new_depth = loop_depth + 1; // entering a loop
 
+            let mut items = Vec::new();
             for v in values {
-                self.env.set_local_var(&var, &v.expand(self.env));
+                items.extend(expand_and_split(v, self.env)?);
+            }
+
+            for value in &items {
+                self.env.set_local_var(var, value);
 
                 match self.execute_node(body, is_background, new_depth) {
                     Err(ShellError::Break(mut remaining)) => {
@@ -1,5 +1,3 @@
-
-use crate::executor::merge_redirects_for_group::merge_command_redirects_with_group;
 use crate::{
     error::ShellError,
     executor::Executor,
@@ -13,19 +11,11 @@ impl<'a> Executor<'a> {
         is_background: bool,
         loop_depth: usize,) -> Result<i32, ShellError> {
 
-        if let AstNode::Group { commands, redirects: group_redirects } = node{
+        if let AstNode::Group { commands, .. } = node{
               let mut last_status = 0;
 
         for cmd in commands {
-            if let AstNode::Command { cmd, args, assignments, redirects: command_redirects } = cmd{
-                let effective_redirects = merge_command_redirects_with_group(group_redirects.to_owned(), command_redirects.to_owned());
-                let new_cmd = AstNode::Command { cmd: cmd.clone(), args: args.clone(), assignments: assignments.clone(), redirects: effective_redirects };
-                last_status =  self.execute_node(
-                    &new_cmd,
-                    is_background,
-                    loop_depth
-                )?;
-            }
+            last_status = self.execute_node(cmd, is_background, loop_depth)?;
             self.env.set_last_status(last_status);
 
         }
@@ -35,4 +25,4 @@ impl<'a> Executor<'a> {
         unreachable!();
     }
 
-}
\ No newline at end of file
+}
@@ -12,6 +12,7 @@ impl<'a> Executor<'a> {
             then_branch,
             elif,
             else_branch,
+            ..
         } = node
         {
             let condition_status = self.execute_node(condition, is_background, loop_depth)?;
@@ -7,7 +7,7 @@ impl<'a> Executor<'a> {
         is_background: bool,
         loop_depth: usize,
     ) -> Result<i32, ShellError> {
-        if let AstNode::While { condition, body } = node {
+        if let AstNode::While { condition, body, .. } = node {
             let mut last_status = 0;
             let new_depth = loop_depth + 1; // entering a loop
 
@@ -1,4 +1,3 @@
-mod merge_redirects_for_group;
 mod exec_command;
 mod exec_group;
 mod exec_sequence;
@@ -10,6 +9,8 @@ mod exec_if;
 mod exec_while;
 mod exec_for;
 mod exec_until;
+mod exec_case;
+mod exec_arithmetic;
 mod parse_level;
 
 
@@ -19,7 +20,10 @@ use nix::unistd::Pid;
 
 use crate::envirement::ShellEnv;
 use crate::error::ShellError;
+use crate::exec::{leave_function, record_pipestatus};
+use crate::expansion::reap_process_substitutions;
 use crate::parser::types::*;
+use crate::redirection::{apply_redirects, restore_shell_fds};
 
 
 
@@ -40,6 +44,23 @@ impl<'a> Executor<'a> {
         node: &AstNode,
         is_background: bool,
         loop_depth: usize,
+    ) -> Result<i32, ShellError> {
+        // a compound command's redirections stay in place while it runs and
+        // are undone whatever it returns
+        let saved = apply_redirects(node.compound_redirects(), self.env)?;
+        self.env.loop_depth = loop_depth;
+        let result = self.run_node(node, is_background, loop_depth);
+        restore_shell_fds(saved);
+        record_pipestatus(node, &result, self.env);
+        reap_process_substitutions(self.env);
+        result
+    }
+
+    fn run_node(
+        &mut self,
+        node: &AstNode,
+        is_background: bool,
+        loop_depth: usize,
     ) -> Result<i32, ShellError> {
         match node {
             AstNode::Command { .. } => self.exec_command(node, is_background),
@@ -50,11 +71,13 @@ impl<'a> Executor<'a> {
             AstNode::And(left, right) => self.exec_and(left, right, is_background, loop_depth),
             AstNode::Or(left, right) => self.exec_or(left, right, is_background, loop_depth),
             AstNode::Not(inner) => self.exec_not(inner, is_background, loop_depth),
-            AstNode::Subshell(inner) => self.exec_subshell(inner, is_background, loop_depth),
+            AstNode::Subshell { body, .. } => self.exec_subshell(body, is_background),
             AstNode::If { .. } => self.exec_if(node, is_background, loop_depth),
             AstNode::For { .. } => self.exec_for(node, is_background, loop_depth),
             AstNode::While { .. } => self.exec_while(node, is_background, loop_depth),
             AstNode::Until { .. } => self.exec_until(node, is_background, loop_depth),
+            AstNode::Case { .. } => self.exec_case(node, is_background, loop_depth),
+            AstNode::ArithmeticCommand(_) => self.exec_arithmetic(node),
             AstNode::Break(level) => {
                 let n = parse_level(level, self.env, "break")?;
                 let n = n.min(loop_depth);
@@ -66,9 +89,15 @@ impl<'a> Executor<'a> {
                 Err(ShellError::Continue(n))
             },
 
-            AstNode::FunctionDef { ..} =>{
-                return Ok(0);
+            AstNode::Return(status) => leave_function(status, self.env),
+
+            AstNode::FunctionDef { name, .. } => {
+                self.env.set_func(name.expand(self.env), node.clone());
+                self.env.set_last_status(0);
+                Ok(0)
             },
+
+            AstNode::Comment { .. } => Ok(self.env.get_last_status()),
         }
     }
 }
@@ -2,6 +2,7 @@ use crate::envirement::ShellEnv;
 use crate::error::ShellError;
 use crate::exec::CommandResult;
 use crate::exec::build_command;
+use crate::redirection::{redirect_shell_fds, restore_shell_fds};
 use nix::fcntl::{FcntlArg, fcntl};
 use nix::sys::signal::signal;
 use nix::sys::signal::SigHandler;
@@ -143,6 +144,8 @@ fn execute_external_with_fork(
             unsafe {
             // Restore Ctrl+C handling in child
                 signal(Signal::SIGINT, SigHandler::SigDfl).unwrap();
+                // ignored by the Rust runtime, a command writing to a closed pipe should die
+                signal(Signal::SIGPIPE, SigHandler::SigDfl).unwrap();
             }
             // Setup standard file descriptors
             if let Some(new_fd) = stdin_new_fd {
@@ -227,37 +230,16 @@ pub fn run_commande(
             None,
             env.clone(),
         );
-        let mut backups: Option<Vec<(u64, i32)>> = None;
-        if let Some(map) = fds_map {
-            backups = Some(
-                map.iter()
-                    .filter_map(|(&fd, _)| match dup(fd as i32) {
-                        Ok(dup_fd) => Some((fd, dup_fd)),
-                        Err(err) => {
-                            eprintln!("Failed to duplicate fd {}: {}", fd, err);
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>(),
-            );
-
-            for (&target_fd, owned_fd) in map {
-                let source_fd = owned_fd.as_raw_fd();
-                dup2(source_fd, target_fd as i32).map_err(|e| {
-                    ShellError::Exec(format!("dup2 failed for fd {}: {}", target_fd, e))
-                })?;
-            }
-        }
         match com {
             Some(val) => {
-                let status = val.execute(env)?;
-                if let Some(back) = backups {
-                    for (fd, backup) in back {
-                        dup2(backup, fd as i32).ok();
-                        close(backup).ok();
-                    }
-                }
-                return Ok(CommandResult::Builtin(status));
+                let saved = match fds_map {
+                    Some(map) => redirect_shell_fds(map)?,
+                    None => Vec::new(),
+                };
+                // the fds come back even when the builtin fails
+                let status = val.execute(env);
+                restore_shell_fds(saved);
+                return Ok(CommandResult::Builtin(status?));
             }
             None => {
                 return Err(ShellError::Exec(format!(
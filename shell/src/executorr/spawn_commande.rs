@@ -1,16 +1,16 @@
-use crate::envirement::ShellEnv;
+use crate::envirement::{ShellEnv, is_name};
 use crate::error::ShellError;
 use crate::exec::CommandResult;
 use crate::exec::CommandType;
-use crate::exec::execute;
+use crate::exec::execute_with_background;
 use crate::exec::get_command_type;
 use crate::executorr::run_commande::run_commande;
-use crate::expansion::expand_and_split;
+use crate::expansion::{close_process_substitutions, expand_and_split, expand_tilde};
 use crate::features::jobs;
 use crate::features::jobs::JobStatus;
-use crate::lexer::types::Word;
-use crate::redirection::setup_redirections_ownedfds;
-use crate::types::Redirect;
+use crate::lexer::types::{QuoteType, Word, WordPart};
+use crate::redirection::{redirect_shell_fds, restore_shell_fds, setup_redirections_ownedfds};
+use crate::types::{AstNode, Redirect};
 use nix::sys::signal::Signal;
 use nix::sys::signal::signal;
 use nix::unistd::Pid;
@@ -125,10 +125,82 @@ pub fn spawn_command(
     env: &mut ShellEnv,
     piping_fds: Option<&HashMap<u64, OwnedFd>>,
     gid: &mut Option<Pid>,
+) -> Result<CommandResult, ShellError> {
+    // `<(...)` and `>(...)` in the words stay open until the command has started
+    let first = env.process_substitutions.len();
+    let result = start_command(cmd, args, assignments, redirects, env, piping_fds, gid);
+    close_process_substitutions(env, first);
+    result
+}
+
+/// The variables a command's prefix assignments hide, with their value and
+/// export flag, or `None` when they were unset.
+type SavedVars = Vec<(String, Option<(String, bool)>)>;
+
+/// Sets the assignments before a builtin or function name for the time it
+/// runs, exported when `export` is set, and returns the values they hide.
+fn assign_prefix(
+    assignments: &[(String, Word)],
+    env: &mut ShellEnv,
+    export: bool,
+) -> Result<SavedVars, ShellError> {
+    let mut saved = Vec::new();
+    for (name, value) in assignments {
+        let value = match expand_tilde(value, env, true).try_expand(env) {
+            Ok(value) => value,
+            Err(e) => {
+                restore_prefix(saved, env);
+                return Err(e);
+            }
+        };
+        saved.push((name.clone(), env.variables.get(name).cloned()));
+        if export {
+            env.set_env_var(name, &value);
+        } else {
+            env.set_local_var(name, &value);
+        }
+    }
+    Ok(saved)
+}
+
+/// Puts back the values `assign_prefix` replaced.
+fn restore_prefix(saved: SavedVars, env: &mut ShellEnv) {
+    for (name, value) in saved.into_iter().rev() {
+        env.arith_vars.remove(&name);
+        match value {
+            Some(value) => env.variables.insert(name, value),
+            None => env.variables.remove(&name),
+        };
+    }
+}
+
+/// Splits a word starting with an unquoted `name=` into the name and the
+/// value word.
+fn split_assignment_word(word: &Word) -> Option<(&str, Word)> {
+    let Some(WordPart::Literal((text, QuoteType::None))) = word.parts.first() else {
+        return None;
+    };
+    let (name, rest) = text.split_once('=').filter(|(name, _)| is_name(name))?;
+    let mut parts = Vec::new();
+    if !rest.is_empty() {
+        parts.push(WordPart::Literal((rest.to_string(), QuoteType::None)));
+    }
+    parts.extend_from_slice(&word.parts[1..]);
+    Some((name, Word { parts, quote: word.quote }))
+}
+
+fn start_command(
+    cmd: &Word,
+    args: &Vec<Word>,
+    assignments: &Vec<(String, Word)>,
+    redirects: &Vec<Redirect>,
+    env: &mut ShellEnv,
+    piping_fds: Option<&HashMap<u64, OwnedFd>>,
+    gid: &mut Option<Pid>,
 ) -> Result<CommandResult, ShellError> {
     // 1. Expand command and args
     let mut all_args: Vec<String> = vec![];
-    let mut expanded_command = expand_and_split(cmd, env);
+    let mut expanded_command = expand_and_split(cmd, env)?;
     let cmd_str = if expanded_command.len() >= 1 {
         expanded_command.remove(0)
     } else {
@@ -136,8 +208,15 @@ pub fn spawn_command(
     };
     all_args.extend(expanded_command);
 
+    // `local name=value` is an assignment, its value is neither split nor globbed
+    let declaration = cmd_str == "local";
     for arg in args {
-        let expanded_args = expand_and_split(arg, env);
+        if let Some((name, value)) = split_assignment_word(arg).filter(|_| declaration) {
+            let value = expand_tilde(&value, env, true).try_expand(env)?;
+            all_args.push(format!("{}={}", name, value));
+            continue;
+        }
+        let expanded_args = expand_and_split(arg, env)?;
         all_args.extend(expanded_args);
     }
 
@@ -176,15 +255,54 @@ pub fn spawn_command(
 
     // 3. Execute the command without waiting
     if !cmd_str.is_empty() {
-        match get_command_type(cmd.expand(env).as_str(), env) {
+        match get_command_type(cmd_str.as_str(), env) {
             CommandType::Function(func) => {
-                let status = execute(&func, env)?;
+                let AstNode::FunctionDef { body, .. } = func else {
+                    unreachable!()
+                };
+                // redirections at the call apply to the whole body
+                let saved_fds = match &merged_fds {
+                    Some(fds_map) => redirect_shell_fds(fds_map)?,
+                    None => Vec::new(),
+                };
+
+                // `x=1 f` exports x to the call only
+                let saved_vars = match assign_prefix(assignments, env, true) {
+                    Ok(saved_vars) => saved_vars,
+                    Err(e) => {
+                        restore_shell_fds(saved_fds);
+                        return Err(e);
+                    }
+                };
+
+                // the arguments are the positional parameters for the call,
+                // and its `local` variables go away when it returns. The body
+                // is in the caller's loops, `break` there leaves the loop
+                let saved = std::mem::replace(&mut env.positional, all_args.clone());
+                env.local_frames.push(Vec::new());
+                let loop_depth = env.loop_depth;
+                let status = match execute_with_background(&body, env, false, loop_depth) {
+                    Err(ShellError::Return(status)) => Ok(status),
+                    // called outside any loop
+                    Err(e @ (ShellError::Break(0) | ShellError::Continue(0))) => {
+                        eprintln!("{}", e);
+                        Ok(0)
+                    }
+                    status => status,
+                };
+                env.pop_local_frame();
+                env.positional = saved;
+                restore_prefix(saved_vars, env);
+                restore_shell_fds(saved_fds);
+                let status = status?;
                 env.set_last_status(status);
                 return Ok(CommandResult::Builtin(status));
             }
 
             CommandType::Builtin => {
-                run_commande(
+                // `IFS= read` sets IFS for the builtin only
+                let saved_vars = assign_prefix(assignments, env, false)?;
+                let result = run_commande(
                     &cmd_str,
                     &all_args,
                     merged_fds.as_ref(),
@@ -192,14 +310,20 @@ pub fn spawn_command(
                     HashMap::new(),
                     env,
                     gid,
-                )
-                // Ok(CommandResult::Builtin())
+                );
+                restore_prefix(saved_vars, env);
+                let result = result?;
+                if let CommandResult::Builtin(status) = result {
+                    env.set_last_status(status);
+                }
+                Ok(result)
             }
 
             CommandType::External(path) => {
                 let mut envs = env.get_environment_only();
                 for ass in assignments.clone() {
-                    envs.insert(ass.0, ass.1.expand(&env));
+                    let value = expand_tilde(&ass.1, env, true).try_expand(env)?;
+                    envs.insert(ass.0, value);
                 }
 
                 match run_commande(&path, &all_args, merged_fds.as_ref(), true, envs, env, gid)? {
@@ -211,16 +335,19 @@ pub fn spawn_command(
                 }
             }
             CommandType::Undefined => {
-                Err(ShellError::Push(format!("Command not found: {}", cmd_str)))
+                Err(ShellError::CommandNotFound(cmd_str.to_string()))
             }
         }
     } else {
-        // Handle variable assignments without command
+        // Handle variable assignments without command, the status is the one
+        // of the last command substitution
         if !assignments.is_empty() {
+            env.set_last_status(0);
             for ass in assignments {
-                env.set_local_var(&ass.0, &ass.1.expand(&env));
+                let value = expand_tilde(&ass.1, env, true).try_expand(env)?;
+                env.set_local_var(&ass.0, &value);
             }
-            return Ok(CommandResult::Builtin(0));
+            return Ok(CommandResult::Builtin(env.get_last_status()));
         }
         return Ok(CommandResult::Builtin(0));
     }
@@ -1,11 +1,554 @@
-use crate::{envirement::ShellEnv, lexer::{types::{QuoteType, Word}}};
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
 
-    pub fn expand_and_split(word: &Word, env: &ShellEnv) -> Vec<String> {
-    let expanded = word.expand(env);
-    if word.quote == QuoteType::None {
-        expanded.split_whitespace().map(|s| s.to_string()).collect()
+use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
+use nix::unistd::{close, dup2, pipe};
+use users::os::unix::UserExt;
+
+use crate::{brace::expand_braces, envirement::{ShellEnv, is_name}, error::ShellError, exec::{execute, fork_shell}, glob::expand_glob, lexer::{tokenize::Tokenizer, types::{ParameterExpansion, ParameterOp, QuoteType, ReplaceMode, Subscript, Word, WordPart}}, parser::Parser, pattern::{escape_pattern, pattern_matches}};
+
+/// Expands a word into fields. Brace and tilde expansion run first, then the
+/// results of unquoted expansions are split on `IFS` and each field goes
+/// through pathname expansion.
+pub fn expand_and_split(word: &Word, env: &mut ShellEnv) -> Result<Vec<String>, ShellError> {
+    let mut fields = Vec::new();
+    for word in expand_braces(word) {
+        let word = expand_tilde(&word, env, false);
+        fields.extend(expand_fields(&word, env)?);
+    }
+    Ok(fields)
+}
+
+fn expand_fields(word: &Word, env: &mut ShellEnv) -> Result<Vec<String>, ShellError> {
+    let ifs = if env.is_set("IFS") {
+        env.get("IFS").unwrap_or_default()
     } else {
-        vec![expanded]
+        " \t\n".to_string()
+    };
+
+    // fields are kept as patterns: quoted text is escaped so it cannot act
+    // as a wildcard. `current` is None until something starts a field, so an
+    // empty unquoted expansion leaves no field behind while `""` does.
+    let mut fields = Vec::new();
+    let mut current: Option<String> = None;
+    push_parts(&word.parts, false, false, &ifs, env, &mut fields, &mut current)?;
+    fields.extend(current);
+
+    Ok(fields.iter().flat_map(|field| expand_glob(field)).collect())
+}
+
+/// Adds `parts` to the fields being built, `quoted` when they sit inside
+/// double quotes. The chosen word of `${name-word}` and `${name+word}` keeps
+/// its own quoting, so `${x:-"a b"}` is one field, while its unquoted text is
+/// the result of an expansion and split, `operand` being set for it.
+fn push_parts(
+    parts: &[WordPart],
+    quoted: bool,
+    operand: bool,
+    ifs: &str,
+    env: &mut ShellEnv,
+    fields: &mut Vec<String>,
+    current: &mut Option<String>,
+) -> Result<(), ShellError> {
+    for part in parts {
+        match part {
+            WordPart::Literal((text, QuoteType::None)) if !quoted && operand => {
+                split_fields(text, ifs, fields, current);
+            }
+            WordPart::Literal((text, QuoteType::None)) if !quoted => {
+                current.get_or_insert_with(String::new).push_str(text);
+            }
+            WordPart::Literal((text, _)) => {
+                current.get_or_insert_with(String::new).push_str(&escape_pattern(text));
+            }
+            WordPart::Quoted(part) => {
+                push_parts(std::slice::from_ref(&**part), true, operand, ifs, env, fields, current)?;
+            }
+            // "$@" makes one field per positional parameter
+            WordPart::VariableSubstitution(name) if quoted && name == "@" => {
+                push_elements(&env.positional, fields, current);
+            }
+            // and "${PIPESTATUS[@]}" one per element
+            WordPart::ParameterExpansion(ParameterExpansion {
+                name,
+                op: ParameterOp::Subscript(Subscript::All),
+            }) if quoted && env.list(name).is_some() => {
+                push_elements(&env.list(name).unwrap_or_default(), fields, current);
+            }
+            WordPart::ParameterExpansion(expansion) if chosen_operand(expansion, env).is_some() => {
+                let word = chosen_operand(expansion, env).map(|word| expand_tilde(word, env, false));
+                let parts = word.map(|word| word.parts).unwrap_or_default();
+                push_parts(&parts, quoted, true, ifs, env, fields, current)?;
+            }
+            _ if quoted => {
+                let value = part.try_expand(env)?;
+                current.get_or_insert_with(String::new).push_str(&escape_pattern(&value));
+            }
+            _ => {
+                let value = part.try_expand(env)?;
+                split_fields(&value, ifs, fields, current);
+            }
+        }
     }
+    Ok(())
+}
+
+/// Puts each element in a field of its own, the first one joining the field
+/// being built and the last one left open for what follows.
+fn push_elements(elements: &[String], fields: &mut Vec<String>, current: &mut Option<String>) {
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            fields.extend(current.take());
+        }
+        current.get_or_insert_with(String::new).push_str(&escape_pattern(element));
+    }
+}
+
+/// The operand word that `${name-word}` or `${name+word}` expands to, `None`
+/// when it gives the parameter's value instead. `${name=word}` is not one of
+/// them: it gives the value it assigned, which is split like any other.
+fn chosen_operand<'a>(expansion: &'a ParameterExpansion, env: &ShellEnv) -> Option<&'a Word> {
+    let name = expansion.name.as_str();
+    let is_set = env.is_set(name);
+    let missing = |colon: bool| !is_set || (colon && env.get(name).unwrap_or_default().is_empty());
+    match &expansion.op {
+        ParameterOp::UseDefault { word, colon } if missing(*colon) => Some(word),
+        ParameterOp::UseAlternative { word, colon } if !missing(*colon) => Some(word),
+        _ => None,
+    }
+}
+
+/// Splits the result of an unquoted expansion on `IFS`. The first piece joins
+/// the field being built and the others start new ones. Runs of `IFS`
+/// whitespace collapse, any other `IFS` character ends a field even if empty.
+fn split_fields(value: &str, ifs: &str, fields: &mut Vec<String>, current: &mut Option<String>) {
+    let is_ifs_space = |c: char| matches!(c, ' ' | '\t' | '\n') && ifs.contains(c);
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !ifs.contains(c) {
+            // a backslash from an expansion is an ordinary character
+            let field = current.get_or_insert_with(String::new);
+            if c == '\\' {
+                field.push('\\');
+            }
+            field.push(c);
+            continue;
+        }
+
+        // one delimiter is the surrounding whitespace plus at most one other character
+        let mut hard = !is_ifs_space(c);
+        while let Some(&next) = chars.peek() {
+            if is_ifs_space(next) || (!hard && ifs.contains(next)) {
+                hard |= !is_ifs_space(next);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        match current.take() {
+            Some(field) => fields.push(field),
+            None if hard => fields.push(String::new()),
+            None => {}
+        }
+    }
+}
+
+/// Expands a word for use as a pattern: quoted literal parts are escaped so
+/// only unquoted text can act as a wildcard.
+pub fn expand_pattern(word: &Word, env: &mut ShellEnv) -> Result<String, ShellError> {
+    let mut pattern = String::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal((text, QuoteType::Single | QuoteType::Double | QuoteType::Escaped)) => {
+                pattern.push_str(&escape_pattern(text));
+            }
+            WordPart::Quoted(part) => pattern.push_str(&escape_pattern(&part.try_expand(env)?)),
+            _ => pattern.push_str(&part.try_expand(env)?),
+        }
+    }
+    Ok(pattern)
+}
+
+/// Tilde expansion of a word. A tilde prefix is an unquoted `~` at the start
+/// of the word up to the first `/`; in an assignment value a prefix may also
+/// follow any `:` and ends at the next one. The replacement is kept as quoted
+/// text so it is neither split nor globbed.
+pub fn expand_tilde(word: &Word, env: &ShellEnv, assignment: bool) -> Word {
+    let mut parts = Vec::new();
+    let mut at_start = true;
+    for (index, part) in word.parts.iter().enumerate() {
+        let WordPart::Literal((text, QuoteType::None)) = part else {
+            parts.push(part.clone());
+            at_start = false;
+            continue;
+        };
+
+        let is_last = index + 1 == word.parts.len();
+        let mut literal = String::new();
+        let mut rest = text.as_str();
+        loop {
+            if at_start && rest.starts_with('~') {
+                // a prefix running into a quoted or expanded part is not expanded
+                let end = rest.find(|c| c == '/' || (assignment && c == ':'));
+                if end.is_some() || is_last {
+                    let end = end.unwrap_or(rest.len());
+                    if let Some(home) = tilde_prefix(&rest[1..end], env) {
+                        if !literal.is_empty() {
+                            parts.push(WordPart::Literal((std::mem::take(&mut literal), QuoteType::None)));
+                        }
+                        parts.push(WordPart::Literal((home, QuoteType::Single)));
+                        rest = &rest[end..];
+                    }
+                }
+            }
+
+            match rest.find(':').filter(|_| assignment) {
+                Some(colon) => {
+                    literal.push_str(&rest[..=colon]);
+                    rest = &rest[colon + 1..];
+                    at_start = true;
+                }
+                None => {
+                    at_start = at_start && rest.is_empty();
+                    literal.push_str(rest);
+                    break;
+                }
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal((literal, QuoteType::None)));
+        }
+    }
+
+    Word {
+        parts,
+        quote: word.quote,
+    }
+}
+
+/// Resolves the text after `~`: nothing for `$HOME`, `+` for `$PWD`, `-` for
+/// `$OLDPWD`, or a login name.
+fn tilde_prefix(name: &str, env: &ShellEnv) -> Option<String> {
+    let variable = |key: &str| env.get(key).filter(|_| env.is_set(key));
+    match name {
+        "" => variable("HOME").or_else(|| {
+            users::get_user_by_uid(users::get_current_uid())
+                .map(|user| user.home_dir().to_string_lossy().into_owned())
+        }),
+        "+" => variable("PWD"),
+        "-" => variable("OLDPWD"),
+        _ => users::get_user_by_name(name)
+            .map(|user| user.home_dir().to_string_lossy().into_owned()),
+    }
+}
+
+/// Runs the command of a `$(...)` or backtick substitution in a forked copy
+/// of the shell, so it sees functions and unexported variables, and returns
+/// its output without the trailing newlines. `$?` is left set to its status.
+pub fn command_substitution(source: &str, env: &mut ShellEnv) -> Result<String, ShellError> {
+    let tokens = Tokenizer::new(source).tokenize()?;
+    let Some(ast) = Parser::new(tokens).parse()? else {
+        return Ok(String::new());
+    };
+
+    let (read_end, write_end) = pipe().map_err(|e| ShellError::Exec(format!("pipe failed: {}", e)))?;
+    let (read_end, write_end) = unsafe { (OwnedFd::from_raw_fd(read_end), OwnedFd::from_raw_fd(write_end)) };
+
+    let child = fork_shell(env, |env| {
+        if dup2(write_end.as_raw_fd(), 1).is_err() {
+            return Ok(1);
+        }
+        drop(write_end);
+        execute(&ast, env)
+    })?;
+    let mut output = Vec::new();
+    File::from(read_end).read_to_end(&mut output)?;
+
+    let status = match waitpid(child, None) {
+        Ok(WaitStatus::Exited(_, code)) => code,
+        Ok(WaitStatus::Signaled(_, signal, _)) => 128 + signal as i32,
+        _ => 1,
+    };
+    env.set_last_status(status);
+    Ok(String::from_utf8_lossy(&output).trim_end_matches('\n').to_string())
+}
+
+/// Starts the command of `<(...)`, or `>(...)` when `output` is set, in a
+/// forked copy of the shell connected by a pipe and returns the `/dev/fd/N`
+/// path of the shell's end. That end stays open until the command using the
+/// word has started, see `close_process_substitutions`.
+pub fn process_substitution(source: &str, output: bool, env: &mut ShellEnv) -> Result<String, ShellError> {
+    let tokens = Tokenizer::new(source).tokenize()?;
+    let ast = Parser::new(tokens).parse()?;
 
-}
\ No newline at end of file
+    let (read_end, write_end) = pipe().map_err(|e| ShellError::Exec(format!("pipe failed: {}", e)))?;
+    let (read_end, write_end) = unsafe { (OwnedFd::from_raw_fd(read_end), OwnedFd::from_raw_fd(write_end)) };
+    let (shell_end, child_end, child_fd) = if output {
+        (write_end, read_end, 0)
+    } else {
+        (read_end, write_end, 1)
+    };
+
+    let child = fork_shell(env, |env| {
+        // a reader of an earlier `>(...)` would otherwise wait for this child too
+        close(shell_end.as_raw_fd()).ok();
+        for (fd, _) in env.process_substitutions.drain(..) {
+            close(fd).ok();
+        }
+        if dup2(child_end.as_raw_fd(), child_fd).is_err() {
+            return Ok(1);
+        }
+        drop(child_end);
+        ast.map_or(Ok(0), |ast| execute(&ast, env))
+    })?;
+    let fd = shell_end.into_raw_fd();
+    env.process_substitutions.push((fd, child));
+    Ok(format!("/dev/fd/{}", fd))
+}
+
+/// Closes the shell's ends of the process substitutions made since `first`,
+/// once the command using them has started. A `>(...)` reader may outlive
+/// the command, so the children are only reaped by
+/// `reap_process_substitutions` once they exit.
+pub fn close_process_substitutions(env: &mut ShellEnv, first: usize) {
+    for (fd, child) in env.process_substitutions.drain(first..) {
+        close(fd).ok();
+        env.substitution_children.push(child);
+    }
+}
+
+/// Reaps the process substitution children that have exited, without
+/// waiting for the others. One the job reaper got first is dropped too.
+pub fn reap_process_substitutions(env: &mut ShellEnv) {
+    env.substitution_children
+        .retain(|child| matches!(waitpid(*child, Some(WaitPidFlag::WNOHANG)), Ok(WaitStatus::StillAlive)));
+}
+
+/// Expands `${name op word}`. The operand words are only expanded when the
+/// operator needs them, so `${x:-$(cmd)}` does not run `cmd` when `x` is set.
+pub fn expand_parameter(expansion: &ParameterExpansion, env: &mut ShellEnv) -> Result<String, ShellError> {
+    let name = expansion.name.as_str();
+    let value = env.get(name).unwrap_or_default();
+    let is_set = env.is_set(name);
+    // without a colon only unset counts, with one an empty value does too
+    let missing = |colon: bool| !is_set || (colon && value.is_empty());
+
+    match &expansion.op {
+        ParameterOp::Length => Ok(value.chars().count().to_string()),
+        ParameterOp::UseDefault { word, colon } => {
+            if missing(*colon) {
+                expand_tilde(word, env, false).try_expand(env)
+            } else {
+                Ok(value)
+            }
+        }
+        ParameterOp::AssignDefault { word, colon } => {
+            if !missing(*colon) {
+                return Ok(value);
+            }
+            if !is_name(name) {
+                return Err(ShellError::Expansion(format!(
+                    "${}: cannot assign in this way",
+                    name
+                )));
+            }
+            let new_value = expand_tilde(word, env, false).try_expand(env)?;
+            env.set_local_var(name, &new_value);
+            Ok(new_value)
+        }
+        ParameterOp::ErrorIfUnset { word, colon } => {
+            if !missing(*colon) {
+                return Ok(value);
+            }
+            let message = word.try_expand(env)?;
+            let message = if message.is_empty() {
+                "parameter null or not set".to_string()
+            } else {
+                message
+            };
+            Err(ShellError::Expansion(format!("{}: {}", name, message)))
+        }
+        ParameterOp::UseAlternative { word, colon } => {
+            if missing(*colon) {
+                Ok(String::new())
+            } else {
+                expand_tilde(word, env, false).try_expand(env)
+            }
+        }
+        ParameterOp::RemovePrefix { pattern, longest } => {
+            let pattern = expand_pattern(pattern, env)?;
+            let mut ends = char_boundaries(&value);
+            if *longest {
+                ends.reverse();
+            }
+            Ok(ends
+                .into_iter()
+                .find(|&end| pattern_matches(&pattern, &value[..end]))
+                .map(|end| value[end..].to_string())
+                .unwrap_or(value))
+        }
+        ParameterOp::RemoveSuffix { pattern, longest } => {
+            let pattern = expand_pattern(pattern, env)?;
+            let mut starts = char_boundaries(&value);
+            if !*longest {
+                starts.reverse();
+            }
+            Ok(starts
+                .into_iter()
+                .find(|&start| pattern_matches(&pattern, &value[start..]))
+                .map(|start| value[..start].to_string())
+                .unwrap_or(value))
+        }
+        ParameterOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        } => {
+            let pattern = expand_pattern(pattern, env)?;
+            let replacement = replacement.try_expand(env)?;
+            let boundaries = char_boundaries(&value);
+            // an anchored pattern takes its longest match, an empty one
+            // matches the empty string at its end of the value
+            let (start, end) = match mode {
+                ReplaceMode::First | ReplaceMode::All => {
+                    if pattern.is_empty() {
+                        return Ok(value);
+                    }
+                    let all = *mode == ReplaceMode::All;
+                    return Ok(replace_matches(&value, &pattern, &replacement, all));
+                }
+                ReplaceMode::Prefix => match boundaries
+                    .iter()
+                    .rev()
+                    .find(|&&end| pattern_matches(&pattern, &value[..end]))
+                {
+                    Some(&end) => (0, end),
+                    None => return Ok(value),
+                },
+                ReplaceMode::Suffix => match boundaries
+                    .iter()
+                    .find(|&&start| pattern_matches(&pattern, &value[start..]))
+                {
+                    Some(&start) => (start, value.len()),
+                    None => return Ok(value),
+                },
+            };
+            Ok(format!("{}{}{}", &value[..start], replacement, &value[end..]))
+        }
+        ParameterOp::Subscript(subscript) => {
+            let items = env.list(name).unwrap_or_else(|| vec![value]);
+            Ok(match subscript {
+                Subscript::Index(index) => items.get(*index).cloned().unwrap_or_default(),
+                Subscript::All => items.join(" "),
+                Subscript::Joined => items.join(&env.ifs_separator()),
+            })
+        }
+    }
+}
+
+/// Every byte index of `text` that starts a char, plus its length.
+fn char_boundaries(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect()
+}
+
+/// Replaces the longest match of `pattern` at the leftmost position, and with
+/// `all` every following non-overlapping match too.
+fn replace_matches(value: &str, pattern: &str, replacement: &str, all: bool) -> String {
+    let boundaries = char_boundaries(value);
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < boundaries.len() {
+        let start = boundaries[i];
+        if start < copied {
+            i += 1;
+            continue;
+        }
+        let matched = boundaries[i..]
+            .iter()
+            .rev()
+            .find(|&&end| end > start && pattern_matches(pattern, &value[start..end]));
+        match matched {
+            Some(&end) => {
+                result.push_str(&value[copied..start]);
+                result.push_str(replacement);
+                copied = end;
+                if !all {
+                    break;
+                }
+            }
+            None => i += 1,
+        }
+    }
+    result.push_str(&value[copied..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::types::Token;
+
+    /// The fields the words of `source` expand to, as arguments of a command.
+    fn fields(source: &str, env: &mut ShellEnv) -> Vec<String> {
+        let tokens = Tokenizer::new(source).tokenize().unwrap();
+        let mut fields = Vec::new();
+        for token in tokens {
+            if let Token::Word(word) = token {
+                fields.extend(expand_and_split(&word, env).unwrap());
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn default_word_keeps_its_double_quotes() {
+        let mut env = ShellEnv::new();
+        assert_eq!(fields("${x:-\"a b\"} 3", &mut env), ["a b", "3"]);
+    }
+
+    #[test]
+    fn quoted_default_word_is_not_globbed() {
+        let mut env = ShellEnv::new();
+        assert_eq!(fields("${u:-\"*\"}", &mut env), ["*"]);
+    }
+
+    #[test]
+    fn default_word_keeps_its_single_quotes() {
+        let mut env = ShellEnv::new();
+        assert_eq!(fields("${u:-'a  b'}", &mut env), ["a  b"]);
+    }
+
+    #[test]
+    fn alternative_word_keeps_its_quotes() {
+        let mut env = ShellEnv::new();
+        env.set_local_var("v", "1");
+        assert_eq!(fields("${v+\"x  y\"} ${u+\"x  y\"}", &mut env), ["x  y"]);
+    }
+
+    #[test]
+    fn quoted_parts_of_an_operator_word_are_not_split_or_globbed() {
+        let mut env = ShellEnv::new();
+        assert_eq!(fields("\"${u:-a  *}\" ${u:-\"a  *\"}x", &mut env), ["a  *", "a  *x"]);
+    }
+
+    #[test]
+    fn unquoted_operand_text_is_split() {
+        let mut env = ShellEnv::new();
+        assert_eq!(fields("${u:-a\"b c\"d} ${u:-x  y}", &mut env), ["ab cd", "x", "y"]);
+        assert_eq!(fields("${y:=\"a  b\"}", &mut env), ["a", "b"]);
+    }
+
+    #[test]
+    fn quoted_list_gives_one_field_per_element() {
+        let mut env = ShellEnv::new();
+        env.pipestatus = vec![1, 0, 2];
+        assert_eq!(fields("\"${PIPESTATUS[@]}\"", &mut env), ["1", "0", "2"]);
+        assert_eq!(fields("\"<${PIPESTATUS[@]}>\"", &mut env), ["<1", "0", "2>"]);
+        assert_eq!(fields("\"${PIPESTATUS[*]}\"", &mut env), ["1 0 2"]);
+    }
+}
@@ -32,7 +32,54 @@ fn file_to_vec(path: String) -> Vec<String> {
             std::process::exit(1);
         }
     };
-    content.lines().map(|line| line.to_string()).collect()
+    decode(&content)
+}
+
+/// Splits the history file back into the commands `encode` wrote.
+fn decode(content: &str) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    let mut continued = false;
+    for line in content.lines() {
+        let (line, continues) = decode_line(line);
+        match entries.last_mut() {
+            Some(last) if continued => {
+                last.push('\n');
+                last.push_str(&line);
+            }
+            _ => entries.push(line),
+        }
+        continued = continues;
+    }
+    entries
+}
+
+/// Writes `command` as one record: backslashes are doubled, then every inner
+/// line ends in a single `\`.
+fn encode(command: &str) -> String {
+    command.replace('\\', "\\\\").replace('\n', "\\\n") + "\n"
+}
+
+/// Undoes `encode` for one line of the file, telling whether the record
+/// continues on the next line. A lone `\` inside the line is kept, as older
+/// files did not double them.
+fn decode_line(line: &str) -> (String, bool) {
+    let mut decoded = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('\\') => {
+                chars.next();
+                decoded.push('\\');
+            }
+            None => return (decoded, true),
+            Some(_) => decoded.push('\\'),
+        }
+    }
+    (decoded, false)
 }
 
 impl History {
@@ -62,7 +109,7 @@ impl History {
             return "".to_owned();
         }
         self.position -= 1;
-        return self.history[self.position as usize].to_owned().replace("\n", "");
+        return self.history[self.position as usize].to_owned();
     }
 
     pub fn next(&mut self) -> String {
@@ -70,7 +117,7 @@ impl History {
             return "".to_owned();
         }
         self.position += 1;
-        return self.history[self.position as usize].to_owned().replace("\n", "");
+        return self.history[self.position as usize].to_owned();
     }
 
     pub fn save(&mut self, command: String) {
@@ -90,7 +137,7 @@ impl History {
             }
         };
 
-        match file.write((command.to_string() + "\n").as_bytes()) {
+        match file.write(encode(&command).as_bytes()) {
             Ok(v) => v,
             Err(e) => {
                 eprintln!("{e}");
@@ -101,3 +148,20 @@ impl History {
         self.position = self.history.len() as i32;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_survive_a_round_trip() {
+        let commands = ["echo a\\", "for i in 1 2\ndo echo \\\\$i\ndone", "printf '\\n'", "x=\\"];
+        let content: String = commands.iter().map(|command| encode(command)).collect();
+        assert_eq!(decode(&content), commands);
+    }
+
+    #[test]
+    fn single_backslashes_from_older_files_are_kept() {
+        assert_eq!(decode("echo \\$x\nls a\\\nb\n"), ["echo \\$x", "ls a\nb"]);
+    }
+}
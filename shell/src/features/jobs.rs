@@ -104,6 +104,7 @@ pub struct Jobs {
     pub current_job: Option<Pid>,
     pub prev_job: Option<Pid>,
     pub order: Vec<Pid>,
+    pub last_background: Option<Pid>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -126,9 +127,17 @@ impl Jobs {
             prev_job: None,
             current_job: None,
             order: vec![],
+            last_background: None,
         }
     }
 
+    /// Adds a job started with `&`. Its last process is what `$!` reports,
+    /// even after the job is gone from the table.
+    pub fn add_background_job(&mut self, job: Job) {
+        self.last_background = Some(job.processes.last().map_or(job.pid, |p| p.pid));
+        self.add_job(job);
+    }
+
     pub fn add_job(&mut self, job: Job) {
         self.current_job = Some(job.pgid);
         self.order.push(job.pgid);
@@ -0,0 +1,163 @@
+use crate::pattern::pattern_matches;
+use std::fs;
+
+/// Pathname expansion of one field. Quoted characters arrive escaped with a
+/// backslash, so only unquoted `*`, `?` and `[...]` act as wildcards.
+///
+/// Matches come back sorted. Names starting with `.` only match when the
+/// pattern component starts with a literal `.`, and a pattern that matches
+/// nothing is returned unchanged. `.` and `..` are never matched, not even
+/// by `.*`, as with bash's default `globskipdots`.
+pub fn expand_glob(pattern: &str) -> Vec<String> {
+    if !has_wildcards(pattern) {
+        return vec![unescape(pattern)];
+    }
+
+    let (mut paths, relative) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in relative.split('/') {
+        let mut next = Vec::new();
+        for path in &paths {
+            if !has_wildcards(component) {
+                next.push(join(path, &unescape(component)));
+                continue;
+            }
+
+            let dir = if path.is_empty() { "." } else { path.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let wants_hidden = component.starts_with('.') || component.starts_with("\\.");
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if name.starts_with('.') && !wants_hidden {
+                    continue;
+                }
+                if pattern_matches(component, &name) {
+                    next.push(join(path, &name));
+                }
+            }
+        }
+        paths = next;
+    }
+
+    // literal components after a wildcard, like `*/Cargo.toml`, may not exist
+    let mut matches: Vec<String> = paths
+        .into_iter()
+        .filter(|path| fs::symlink_metadata(path).is_ok())
+        .collect();
+    if matches.is_empty() {
+        return vec![unescape(pattern)];
+    }
+    matches.sort();
+    matches
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else if path.ends_with('/') {
+        format!("{}{}", path, name)
+    } else {
+        format!("{}/{}", path, name)
+    }
+}
+
+/// Whether the pattern holds an unescaped `*`, `?` or a closed `[...]`.
+fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' => return true,
+            '[' if chars.clone().skip(1).any(|c| c == ']') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Removes the backslashes that protect quoted characters.
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(next) => text.push(next),
+                None => text.push('\\'),
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// A fresh directory holding empty files at `paths`.
+    fn tree(name: &str, paths: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("push-glob-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for path in paths {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    fn glob(root: &Path, pattern: &str) -> Vec<String> {
+        let prefix = format!("{}/", root.display());
+        expand_glob(&format!("{}{}", prefix, pattern))
+            .into_iter()
+            .map(|path| path.strip_prefix(&prefix).unwrap_or(&path).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn matches_are_sorted() {
+        let root = tree("sorted", &["c.rs", "a.rs", "B.rs", "b.txt", "d/x.rs", "a/x.rs"]);
+        assert_eq!(glob(&root, "*.rs"), ["B.rs", "a.rs", "c.rs"]);
+        assert_eq!(glob(&root, "*/x.rs"), ["a/x.rs", "d/x.rs"]);
+        assert_eq!(glob(&root, "[ab].*"), ["a.rs", "b.txt"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn dotfiles_need_a_leading_dot() {
+        let root = tree("dot", &[".hidden", "shown", ".rc/x"]);
+        assert_eq!(glob(&root, "*"), ["shown"]);
+        assert_eq!(glob(&root, ".*"), [".hidden", ".rc"]);
+        assert_eq!(glob(&root, "\\.h*"), [".hidden"]);
+        assert_eq!(glob(&root, "?hidden"), ["?hidden"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn dot_and_dot_dot_are_skipped() {
+        let root = tree("dots", &[".rc/x"]);
+        assert_eq!(glob(&root, ".*"), [".rc"]);
+        assert_eq!(glob(&root, ".?"), [".?"]);
+        assert_eq!(glob(&root, ".*/x"), [".rc/x"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn no_match_and_quoted_wildcards_stay_literal() {
+        let root = tree("literal", &["a*", "ab"]);
+        assert_eq!(glob(&root, "*.none"), ["*.none"]);
+        assert_eq!(glob(&root, "a\\*"), ["a*"]);
+        assert_eq!(glob(&root, "*/missing"), ["*/missing"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,38 +1,138 @@
 use crate::error::ShellError;
-pub use crate::lexer::types::{QuoteType, State, Token, Word, WordPart};
+pub use crate::lexer::types::{
+    ParameterExpansion, ParameterOp, QuoteType, ReplaceMode, Span, Subscript, State, Token, Word, WordPart,
+};
 use std::iter::Peekable;
 use std::str::Chars;
 
+/// The input's characters together with the byte offset of the next one.
+#[derive(Debug, Clone)]
+pub struct SourceChars<'a> {
+    chars: Peekable<Chars<'a>>,
+    pub offset: usize,
+}
+
+impl<'a> SourceChars<'a> {
+    pub fn peek(&mut self) -> Option<&char> {
+        self.chars.peek()
+    }
+}
+
+impl<'a> Iterator for SourceChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.offset += c.len_utf8();
+        Some(c)
+    }
+}
+
 #[derive(Debug)]
 pub struct Tokenizer<'a> {
-    pub chars: Peekable<Chars<'a>>,
+    pub chars: SourceChars<'a>,
+    source: &'a str,
+    // Indexes of the `<<` tokens whose body starts after the current line
+    heredocs: Vec<usize>,
+    // Offset where the token being read started
+    token_start: usize,
+    /// The span of each token returned by `tokenize`, in the same order.
+    pub spans: Vec<Span>,
+    /// The span of each comment `tokenize` skipped, `#` included.
+    pub comments: Vec<Span>,
 }
 
 impl<'a> Tokenizer<'a> {
     pub fn new(input: &'a str) -> Self {
         Tokenizer {
-            chars: input.chars().peekable(),
+            chars: SourceChars {
+                chars: input.chars().peekable(),
+                offset: 0,
+            },
+            source: input,
+            heredocs: Vec::new(),
+            token_start: 0,
+            spans: Vec::new(),
+            comments: Vec::new(),
         }
     }
 
+    /// Splits the input into tokens and records their spans. A syntax error
+    /// carries the span where the offending token started.
     pub fn tokenize(&mut self) -> Result<Vec<Token>, ShellError> {
+        self.spans.clear();
+        self.comments.clear();
+        match self.read_tokens() {
+            Ok(tokens) => {
+                Span::locate_all(self.source, &mut self.spans);
+                Span::locate_all(self.source, &mut self.comments);
+                Ok(tokens)
+            }
+            Err(error @ ShellError::Syntax(_)) => {
+                let mut span = [Span {
+                    start: self.token_start,
+                    end: self.token_start,
+                    ..Span::default()
+                }];
+                Span::locate_all(self.source, &mut span);
+                Err(ShellError::Located(Box::new(error), span[0]))
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Gives the tokens pushed since `from` their spans: a word ends where
+    /// the current character `at` starts, an operator ends at `end`.
+    fn mark_spans(&mut self, tokens: &[Token], from: usize, at: usize, end: usize) {
+        let mut start = self.token_start;
+        for token in &tokens[from..] {
+            let end = match token {
+                Token::Word(_) => at,
+                Token::Newline => start + 1,
+                _ => end,
+            };
+            self.spans.push(Span {
+                start,
+                end: end.max(start),
+                ..Span::default()
+            });
+            start = at;
+        }
+        if tokens.len() > from {
+            self.token_start = at;
+        }
+    }
+
+    fn read_tokens(&mut self) -> Result<Vec<Token>, ShellError> {
         let mut tokens = Vec::new();
         let mut state = State::Default;
         let mut buffer = (String::new(), QuoteType::None);
         let mut parts: Vec<WordPart> = vec![];
         let mut where_im_at = QuoteType::None;
+        self.token_start = 0;
 
         while let Some(&c) = self.chars.peek() {
+            let at = self.chars.offset;
+            let pushed = tokens.len();
+            if state == State::Default && buffer.0.is_empty() && parts.is_empty() {
+                self.token_start = at;
+            }
             match (&mut state, c) {
                 (State::Default | State::InWord, '\\') => {
                     self.chars.next();
+                    // line continuation, both characters disappear
+                    if let Some('\n') = self.chars.peek() {
+                        self.chars.next();
+                        continue;
+                    }
+                    state = State::InWord;
                     if let Some(next) = self.chars.next() {
-                        match next {
-                            '\\' => buffer.0.push('\\'),
-                            ' ' => buffer.0.push(' '),
-                            '$' => buffer.0.push('$'),
-                            other => buffer.0.push(other),
+                        // an escaped character is quoted, keep it in its own part
+                        if !buffer.0.is_empty() {
+                            parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+                            buffer.0.clear();
                         }
+                        parts.push(WordPart::Literal((next.to_string(), QuoteType::Escaped)));
                     } else {
                         buffer.0.push('\\');
                     }
@@ -42,8 +142,8 @@ impl<'a> Tokenizer<'a> {
                     self.chars.next();
                     if let Some(next) = self.chars.next() {
                         match next {
-                            '\\' => buffer.0.push('\\'),
-                            '"' => buffer.0.push('"'),
+                            '\\' | '"' | '$' | '`' => buffer.0.push(next),
+                            '\n' => {}
                             other => {
                                 buffer.0.push('\\');
                                 buffer.0.push(other)
@@ -78,81 +178,25 @@ impl<'a> Tokenizer<'a> {
                     }
                     if c == '\n' {
                         tokens.push(Token::Newline);
+                        self.read_heredoc_bodies(&mut tokens)?;
                     }
                     state = State::Default;
                 }
 
                 (State::InDoubleQuote | State::Default | State::InWord, '$') => {
                     self.chars.next();
-                    if !buffer.0.is_empty() {
-                        parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
-                        buffer.0.clear();
-                    }
-                    if let Some(c) = self.chars.peek() {
-                        match *c {
-                            '{' => {
-                                self.chars.next();
-                                if !buffer.0.is_empty() {
-                                    parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
-                                    buffer.0.clear();
-                                }
-                                let mut var = String::new();
-                                self.read_until_matching("{", "}", &mut var)?;
-                                parts.push(WordPart::VariableSubstitution(var));
-                            }
-                            '(' => {
-                                self.chars.next();
-                                if !buffer.0.is_empty() {
-                                    parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
-                                    buffer.0.clear();
-                                }
-                                if let Some('(') = self.chars.peek() {
-                                    self.chars.next();
-                                    let mut expr = String::new();
-                                    self.read_until_matching("((", "))", &mut expr)?;
-                                    parts.push(WordPart::ArithmeticSubstitution(expr));
-                                } else {
-                                    let mut cmd = String::new();
-                                    self.read_until_matching("(", ")", &mut cmd)?;
-                                    parts.push(WordPart::CommandSubstitution(cmd));
-                                }
-                            }
-                            c if c.is_alphanumeric() || c == '_' || c == '?' => {
-                                let mut var = String::new();
-                                if c.is_ascii_digit() {
-                                    var.push(c);
-                                    self.chars.next();
-                                    while let Some(&ch) = self.chars.peek() {
-                                        if ch.is_ascii_digit() {
-                                            var.push(ch);
-                                            self.chars.next();
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                } else {
-                                    while let Some(&ch) = self.chars.peek() {
-                                        if ch.is_alphanumeric() || ch == '_' || ch == '?' {
-                                            var.push(ch);
-                                            self.chars.next();
-                                        } else {
-                                            break;
-                                        }
-                                    }
-                                }
-                                parts.push(WordPart::VariableSubstitution(var));
-                            }
-                            _ => buffer.0.push('$'),
-                        }
-                    } else {
-                        buffer.0.push('$');
+                    self.read_dollar(&mut buffer, &mut parts)?;
+                    if state == State::Default {
+                        state = State::InWord;
                     }
                 }
 
-                (State::Default, '~') => {
+                (State::InDoubleQuote | State::Default | State::InWord, '`') => {
                     self.chars.next();
-                    state = State::InWord;
-                    parts.push(WordPart::VariableSubstitution(String::from("~")));
+                    self.read_backtick(&mut buffer, &mut parts)?;
+                    if state == State::Default {
+                        state = State::InWord;
+                    }
                 }
 
                 (State::Default, '{') => {
@@ -173,8 +217,8 @@ impl<'a> Tokenizer<'a> {
                             state = State::InWord;
                         }
                         None => {
-                            buffer.0.push('{');
-                            state = State::InWord;
+                            tokens.push(Token::OpenBrace);
+                            state = State::Default;
                         }
                     }
                 }
@@ -206,7 +250,15 @@ impl<'a> Tokenizer<'a> {
 
                 (State::Default, '(') => {
                     self.chars.next();
-                    tokens.push(Token::OpenParen);
+                    if let Some('(') = self.chars.peek() {
+                        // `(( expr ))` arithmetic command
+                        self.chars.next();
+                        let mut expr = String::new();
+                        self.read_arithmetic(&mut expr)?;
+                        tokens.push(Token::Arithmetic(expr));
+                    } else {
+                        tokens.push(Token::OpenParen);
+                    }
                     state = State::Default;
                 }
 
@@ -230,11 +282,13 @@ impl<'a> Tokenizer<'a> {
                         parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
                         buffer.0.clear();
                     }
+                    buffer.1 = QuoteType::Double;
                     state = State::InDoubleQuote;
                 }
 
                 (State::Default, '\'') => {
                     self.chars.next();
+                    buffer.1 = QuoteType::Single;
                     state = State::InSingleQuote;
                     if where_im_at == QuoteType::None {
                         where_im_at = QuoteType::Double
@@ -246,16 +300,23 @@ impl<'a> Tokenizer<'a> {
                         parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
                         buffer.0.clear();
                     }
+                    buffer.1 = QuoteType::Single;
                     state = State::InSingleQuote;
                 }
 
                 (State::Default, '#') => {
-                    self.chars.next();
-                    while let Some(c) = self.chars.next() {
+                    // the newline ends the comment but still separates commands
+                    while let Some(&c) = self.chars.peek() {
                         if c == '\n' {
                             break;
                         }
+                        self.chars.next();
                     }
+                    self.comments.push(Span {
+                        start: at,
+                        end: self.chars.offset,
+                        ..Span::default()
+                    });
                 }
                 (State::Default, '&') => {
                     self.chars.next();
@@ -290,7 +351,15 @@ impl<'a> Tokenizer<'a> {
                 }
                 (State::Default, '>') => {
                     self.chars.next();
-                    state = State::MaybeRedirectOut2;
+                    if self.chars.peek() == Some(&'(') {
+                        self.chars.next();
+                        let mut command = String::new();
+                        self.read_until_matching('(', ')', &mut command)?;
+                        parts.push(WordPart::ProcessSubstitution { command, output: true });
+                        state = State::InWord;
+                    } else {
+                        state = State::MaybeRedirectOut2;
+                    }
                 }
                 (State::MaybeRedirectOut2, '&') => {
                     buffer.0.push('&');
@@ -309,7 +378,15 @@ impl<'a> Tokenizer<'a> {
                 }
                 (State::Default, '<') => {
                     self.chars.next();
-                    state = State::MaybeRedirectIn2;
+                    if self.chars.peek() == Some(&'(') {
+                        self.chars.next();
+                        let mut command = String::new();
+                        self.read_until_matching('(', ')', &mut command)?;
+                        parts.push(WordPart::ProcessSubstitution { command, output: false });
+                        state = State::InWord;
+                    } else {
+                        state = State::MaybeRedirectIn2;
+                    }
                 }
                 (State::MaybeRedirectIn2, '&') => {
                     buffer.0.push('&');
@@ -319,7 +396,12 @@ impl<'a> Tokenizer<'a> {
                 }
                 (State::MaybeRedirectIn2, '<') => {
                     self.chars.next();
-                    tokens.push(Token::RedirectHereDoc);
+                    self.push_heredoc(&mut tokens, None);
+                    state = State::Default;
+                }
+                (State::MaybeRedirectIn2, '>') => {
+                    self.chars.next();
+                    tokens.push(Token::RedirectReadWrite(None));
                     state = State::Default;
                 }
                 (State::MaybeRedirectIn2, _) => {
@@ -340,7 +422,12 @@ impl<'a> Tokenizer<'a> {
                         where_im_at = QuoteType::None;
                         parts.clear();
                     }
-                    tokens.push(Token::Semicolon);
+                    if let Some(';') = self.chars.peek() {
+                        self.chars.next();
+                        tokens.push(Token::DoubleSemicolon);
+                    } else {
+                        tokens.push(Token::Semicolon);
+                    }
                     state = State::Default;
                 }
                 (State::Default, _) => {
@@ -348,7 +435,7 @@ impl<'a> Tokenizer<'a> {
                     buffer.0.push(c);
                     state = State::InWord;
                 }
-                (State::InWord, ' ' | '\t' | '\n' | '|' | ';' | '&' | '!' | '(' | ')') => {
+                (State::InWord, ' ' | '\t' | '\n' | '|' | ';' | '&' | '(' | ')') => {
                     if !buffer.0.is_empty() {
                         parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
                         buffer.0.clear();
@@ -446,7 +533,8 @@ impl<'a> Tokenizer<'a> {
                 }
                 (State::InDoubleQuote, '"') => {
                     self.chars.next();
-                    if !buffer.0.is_empty() {
+                    // `""` still makes an (empty) field
+                    if !buffer.0.is_empty() || !matches!(parts.last(), Some(WordPart::Quoted(_))) {
                         parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
                         buffer.0.clear();
                     }
@@ -460,10 +548,9 @@ impl<'a> Tokenizer<'a> {
                 }
                 (State::InSingleQuote, '\'') => {
                     self.chars.next();
-                    if !buffer.0.is_empty() {
-                        parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
-                        buffer.0.clear();
-                    }
+                    parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+                    buffer.0.clear();
+                    buffer.1 = QuoteType::None;
                     state = State::InWord;
                 }
                 (State::InSingleQuote, c) => {
@@ -491,9 +578,15 @@ impl<'a> Tokenizer<'a> {
                     tokens.push(Token::RedirectInFd(*fd_num));
                     state = State::InWord;
                 }
-                (State::MaybeRedirectIn2Fd(_), '<') => {
+                (State::MaybeRedirectIn2Fd(fd_num), '<') => {
+                    let fd = Some(*fd_num);
                     self.chars.next();
-                    tokens.push(Token::RedirectHereDoc);
+                    self.push_heredoc(&mut tokens, fd);
+                    state = State::Default;
+                }
+                (State::MaybeRedirectIn2Fd(fd_num), '>') => {
+                    self.chars.next();
+                    tokens.push(Token::RedirectReadWrite(Some(*fd_num)));
                     state = State::Default;
                 }
                 (State::MaybeRedirectIn2Fd(fd_num), _) => {
@@ -501,8 +594,12 @@ impl<'a> Tokenizer<'a> {
                     state = State::Default;
                 }
             }
+            let end = self.chars.offset;
+            self.mark_spans(&tokens, pushed, at, end);
         }
 
+        let end = self.source.len();
+        let pushed = tokens.len();
         if !buffer.0.is_empty() {
             parts.push(WordPart::Literal((buffer.0, buffer.1)));
         }
@@ -516,6 +613,10 @@ impl<'a> Tokenizer<'a> {
         if state == State::InDoubleQuote {
             return Err(ShellError::Syntax("missing quote: \"".to_string()));
         }
+        // a here-document whose body has not started yet needs more input
+        if !self.heredocs.is_empty() {
+            return Err(ShellError::UnexpectedEof);
+        }
         if state == State::InSingleQuote {
             return Err(ShellError::Syntax("missing quote: '".to_string()));
         }
@@ -535,62 +636,637 @@ impl<'a> Tokenizer<'a> {
             }
             _ => {}
         }
+        self.mark_spans(&tokens, pushed, end, end);
 
+        self.token_start = end;
         tokens.push(Token::Eof);
+        self.mark_spans(&tokens, tokens.len() - 1, end, end);
         Ok(tokens)
     }
 
+    /// Called after `<<`, a third `<` makes it a here-string instead.
+    fn push_heredoc(&mut self, tokens: &mut Vec<Token>, fd: Option<u64>) {
+        if self.chars.peek() == Some(&'<') {
+            self.chars.next();
+            tokens.push(Token::RedirectHereString(fd));
+            return;
+        }
+        let strip_tabs = self.chars.peek() == Some(&'-');
+        if strip_tabs {
+            self.chars.next();
+        }
+        self.heredocs.push(tokens.len());
+        tokens.push(Token::RedirectHereDoc { fd, strip_tabs });
+    }
+
+    /// Reads the bodies of the here-documents opened on the line that just
+    /// ended, each one replaces its delimiter word.
+    fn read_heredoc_bodies(&mut self, tokens: &mut [Token]) -> Result<(), ShellError> {
+        for index in std::mem::take(&mut self.heredocs) {
+            let strip_tabs = matches!(
+                tokens[index],
+                Token::RedirectHereDoc {
+                    strip_tabs: true,
+                    ..
+                }
+            );
+            // without a delimiter the parser reports the error
+            let delimiter = match tokens.get(index + 1) {
+                Some(Token::Word(word)) => word.clone(),
+                _ => continue,
+            };
+
+            let (end, quoted) = heredoc_delimiter(&delimiter);
+
+            let mut body = String::new();
+            loop {
+                if self.chars.peek().is_none() {
+                    return Err(ShellError::UnexpectedEof);
+                }
+                let mut line: String = self.chars.by_ref().take_while(|&c| c != '\n').collect();
+                if strip_tabs {
+                    line = line.trim_start_matches('\t').to_string();
+                }
+                if line == end {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+
+            let body = if quoted {
+                Word {
+                    parts: vec![WordPart::Literal((body, QuoteType::Single))],
+                    quote: QuoteType::Single,
+                }
+            } else {
+                Self::heredoc_word(&body)?
+            };
+            tokens[index + 1] = Token::HereDoc { delimiter, body };
+        }
+        Ok(())
+    }
+
+    /// Splits an unquoted here-document body into parts. Only `$` expansions
+    /// and the backslash escapes `\$`, `\\`, `` \` `` and line continuation apply.
+    fn heredoc_word(body: &str) -> Result<Word, ShellError> {
+        Self::double_quoted_word(body, false)
+    }
+
+    /// Splits the text of `$(( ))` or `(( ))` into parts like a here-document
+    /// body. Its own double quotes are removed, the ones inside a nested
+    /// substitution are read with it.
+    pub fn arithmetic_word(text: &str) -> Result<Word, ShellError> {
+        Self::double_quoted_word(text, true)
+    }
+
+    fn double_quoted_word(body: &str, remove_quotes: bool) -> Result<Word, ShellError> {
+        let mut tokenizer = Tokenizer::new(body);
+        let mut buffer = (String::new(), QuoteType::Double);
+        let mut parts = vec![];
+        while let Some(c) = tokenizer.chars.next() {
+            match c {
+                '"' if remove_quotes => {}
+                '$' => tokenizer.read_dollar(&mut buffer, &mut parts)?,
+                '`' => tokenizer.read_backtick(&mut buffer, &mut parts)?,
+                '\\' => match tokenizer.chars.peek() {
+                    Some(&next @ ('$' | '`' | '\\')) => {
+                        tokenizer.chars.next();
+                        buffer.0.push(next);
+                    }
+                    Some('\n') => {
+                        tokenizer.chars.next();
+                    }
+                    _ => buffer.0.push('\\'),
+                },
+                c => buffer.0.push(c),
+            }
+        }
+        if !buffer.0.is_empty() {
+            parts.push(WordPart::Literal(buffer));
+        }
+        Ok(Word {
+            parts,
+            quote: QuoteType::Double,
+        })
+    }
+
+    /// Reads the expansion following a `$` that was just consumed, flushing
+    /// the pending literal first. Inside double quotes the expansion is
+    /// wrapped in `WordPart::Quoted`.
+    fn read_dollar(
+        &mut self,
+        buffer: &mut (String, QuoteType),
+        parts: &mut Vec<WordPart>,
+    ) -> Result<(), ShellError> {
+        if !buffer.0.is_empty() {
+            parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+            buffer.0.clear();
+        }
+        let first_new = parts.len();
+        if let Some(c) = self.chars.peek() {
+            match *c {
+                '{' => {
+                    self.chars.next();
+                    if !buffer.0.is_empty() {
+                        parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+                        buffer.0.clear();
+                    }
+                    let mut inner = String::new();
+                    self.read_until_matching('{', '}', &mut inner)?;
+                    parts.push(Self::parse_parameter(&inner)?);
+                }
+                '(' => {
+                    self.chars.next();
+                    if !buffer.0.is_empty() {
+                        parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+                        buffer.0.clear();
+                    }
+                    if let Some('(') = self.chars.peek() {
+                        self.chars.next();
+                        let mut expr = String::new();
+                        self.read_arithmetic(&mut expr)?;
+                        parts.push(WordPart::ArithmeticSubstitution(expr));
+                    } else {
+                        let mut cmd = String::new();
+                        self.read_until_matching('(', ')', &mut cmd)?;
+                        parts.push(WordPart::CommandSubstitution {
+                            command: cmd,
+                            backtick: false,
+                        });
+                    }
+                }
+                // special parameters and `$1`..`$9` are a single character,
+                // `$10` is `${1}0`
+                c if c.is_ascii_digit() || matches!(c, '?' | '#' | '@' | '*' | '$' | '!' | '-') => {
+                    self.chars.next();
+                    parts.push(WordPart::VariableSubstitution(c.to_string()));
+                }
+                c if c.is_alphanumeric() || c == '_' => {
+                    let mut var = String::new();
+                    while let Some(&ch) = self.chars.peek() {
+                        if ch.is_alphanumeric() || ch == '_' {
+                            var.push(ch);
+                            self.chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    parts.push(WordPart::VariableSubstitution(var));
+                }
+                _ => buffer.0.push('$'),
+            }
+        } else {
+            buffer.0.push('$');
+        }
+        if buffer.1 == QuoteType::Double && parts.len() > first_new {
+            let part = parts.remove(first_new);
+            parts.push(WordPart::Quoted(Box::new(part)));
+        }
+        Ok(())
+    }
+
+    /// Reads a legacy `` `command` `` substitution after the opening backtick.
+    /// Inside it a backslash only escapes `` ` ``, `$` and `\\`, and also `"`
+    /// when the substitution is itself in double quotes.
+    fn read_backtick(
+        &mut self,
+        buffer: &mut (String, QuoteType),
+        parts: &mut Vec<WordPart>,
+    ) -> Result<(), ShellError> {
+        if !buffer.0.is_empty() {
+            parts.push(WordPart::Literal((buffer.0.clone(), buffer.1)));
+            buffer.0.clear();
+        }
+
+        let mut command = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(ShellError::Syntax("missing quote: `".to_string())),
+                Some('`') => break,
+                Some('\\') => match self.chars.peek() {
+                    Some(&next @ ('`' | '$' | '\\')) => {
+                        self.chars.next();
+                        command.push(next);
+                    }
+                    Some('"') if buffer.1 == QuoteType::Double => {
+                        self.chars.next();
+                        command.push('"');
+                    }
+                    _ => command.push('\\'),
+                },
+                Some(c) => command.push(c),
+            }
+        }
+
+        let part = WordPart::CommandSubstitution {
+            command,
+            backtick: true,
+        };
+        if buffer.1 == QuoteType::Double {
+            parts.push(WordPart::Quoted(Box::new(part)));
+        } else {
+            parts.push(part);
+        }
+        Ok(())
+    }
+
+    /// Parses the text between `${` and `}`.
+    fn parse_parameter(inner: &str) -> Result<WordPart, ShellError> {
+        let bad_substitution = || ShellError::Syntax(format!("${{{}}}: bad substitution", inner));
+
+        // `${#name}` is a length, but `${#}` and `${#:-word}` use the parameter `#`
+        if let Some(name) = inner.strip_prefix('#')
+            && !name.is_empty()
+            && parameter_name_len(name) == name.len()
+        {
+            return Ok(WordPart::ParameterExpansion(ParameterExpansion {
+                name: name.to_string(),
+                op: ParameterOp::Length,
+            }));
+        }
+
+        let name_len = parameter_name_len(inner);
+        if name_len == 0 {
+            return Err(bad_substitution());
+        }
+        let (name, rest) = inner.split_at(name_len);
+        if rest.is_empty() {
+            return Ok(WordPart::VariableSubstitution(name.to_string()));
+        }
+        if let Some(index) = rest.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            let subscript = match index {
+                "@" => Subscript::All,
+                "*" => Subscript::Joined,
+                _ => Subscript::Index(index.parse().map_err(|_| bad_substitution())?),
+            };
+            return Ok(WordPart::ParameterExpansion(ParameterExpansion {
+                name: name.to_string(),
+                op: ParameterOp::Subscript(subscript),
+            }));
+        }
+
+        let (colon, rest) = match rest.strip_prefix(':') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let mut chars = rest.chars();
+        let op_char = chars.next();
+        let operand = chars.as_str();
+
+        let op = match (op_char, colon) {
+            (Some('-'), _) => ParameterOp::UseDefault {
+                word: Self::operand_word(operand)?,
+                colon,
+            },
+            (Some('='), _) => ParameterOp::AssignDefault {
+                word: Self::operand_word(operand)?,
+                colon,
+            },
+            (Some('?'), _) => ParameterOp::ErrorIfUnset {
+                word: Self::operand_word(operand)?,
+                colon,
+            },
+            (Some('+'), _) => ParameterOp::UseAlternative {
+                word: Self::operand_word(operand)?,
+                colon,
+            },
+            (Some('#'), false) => match operand.strip_prefix('#') {
+                Some(pattern) => ParameterOp::RemovePrefix {
+                    pattern: Self::operand_word(pattern)?,
+                    longest: true,
+                },
+                None => ParameterOp::RemovePrefix {
+                    pattern: Self::operand_word(operand)?,
+                    longest: false,
+                },
+            },
+            (Some('%'), false) => match operand.strip_prefix('%') {
+                Some(pattern) => ParameterOp::RemoveSuffix {
+                    pattern: Self::operand_word(pattern)?,
+                    longest: true,
+                },
+                None => ParameterOp::RemoveSuffix {
+                    pattern: Self::operand_word(operand)?,
+                    longest: false,
+                },
+            },
+            (Some('/'), false) => {
+                let (mode, operand) = match operand.chars().next() {
+                    Some('/') => (ReplaceMode::All, &operand[1..]),
+                    Some('#') => (ReplaceMode::Prefix, &operand[1..]),
+                    Some('%') => (ReplaceMode::Suffix, &operand[1..]),
+                    _ => (ReplaceMode::First, operand),
+                };
+                let (pattern, replacement) = split_replacement(operand);
+                ParameterOp::Replace {
+                    pattern: Self::operand_word(pattern)?,
+                    replacement: Self::operand_word(replacement)?,
+                    mode,
+                }
+            }
+            _ => return Err(bad_substitution()),
+        };
+
+        Ok(WordPart::ParameterExpansion(ParameterExpansion {
+            name: name.to_string(),
+            op,
+        }))
+    }
+
+    /// Splits the word of a `${name op word}` into parts. Quotes, backslashes
+    /// and `$` work as in a normal word, but blanks do not end it.
+    fn operand_word(text: &str) -> Result<Word, ShellError> {
+        let mut tokenizer = Tokenizer::new(text);
+        let mut buffer = (String::new(), QuoteType::None);
+        let mut parts = vec![];
+        while let Some(c) = tokenizer.chars.next() {
+            match c {
+                '$' => tokenizer.read_dollar(&mut buffer, &mut parts)?,
+                '`' => tokenizer.read_backtick(&mut buffer, &mut parts)?,
+                '\\' => {
+                    flush_literal(&mut buffer, &mut parts);
+                    match tokenizer.chars.next() {
+                        Some(next) => {
+                            parts.push(WordPart::Literal((next.to_string(), QuoteType::Escaped)))
+                        }
+                        None => buffer.0.push('\\'),
+                    }
+                }
+                '\'' => {
+                    flush_literal(&mut buffer, &mut parts);
+                    let quoted: String = tokenizer.chars.by_ref().take_while(|&c| c != '\'').collect();
+                    parts.push(WordPart::Literal((quoted, QuoteType::Single)));
+                }
+                '"' => {
+                    flush_literal(&mut buffer, &mut parts);
+                    buffer.1 = QuoteType::Double;
+                    while let Some(c) = tokenizer.chars.next() {
+                        match c {
+                            '"' => break,
+                            '$' => tokenizer.read_dollar(&mut buffer, &mut parts)?,
+                            '`' => tokenizer.read_backtick(&mut buffer, &mut parts)?,
+                            '\\' => match tokenizer.chars.next() {
+                                Some(next @ ('$' | '`' | '"' | '\\')) => buffer.0.push(next),
+                                Some(other) => {
+                                    buffer.0.push('\\');
+                                    buffer.0.push(other);
+                                }
+                                None => buffer.0.push('\\'),
+                            },
+                            c => buffer.0.push(c),
+                        }
+                    }
+                    flush_literal(&mut buffer, &mut parts);
+                    buffer.1 = QuoteType::None;
+                }
+                c => buffer.0.push(c),
+            }
+        }
+        flush_literal(&mut buffer, &mut parts);
+        Ok(Word {
+            parts,
+            quote: QuoteType::None,
+        })
+    }
+
+    /// Reads up to the `close` that matches an already consumed `open`,
+    /// balancing nested pairs. Inside `$( )` quoted text, backslash escapes
+    /// and comments are copied without being counted, and the `)` ending a
+    /// case pattern does not close the substitution.
     fn read_until_matching(
         &mut self,
-        start: &str,
-        end: &str,
+        open: char,
+        close: char,
         buffer: &mut String,
     ) -> Result<(), ShellError> {
-        let start_len = start.len();
-        let end_len = end.len();
+        let command = open == '(';
         let mut depth = 1;
-        while let Some(_) = self.chars.peek() {
-            if self.peek_matches(start) {
-                for _ in 0..start_len {
-                    let char = match self.chars.next() {
-                        Some(val) => val,
-                        None => return Err(ShellError::UnexpectedEof),
-                    };
-                    buffer.push(char);
+        // the depths at which each `case` still waiting for its `esac` started
+        let mut cases: Vec<usize> = Vec::new();
+        let mut word = String::new();
+        let mut word_quoted = false;
+        let mut command_position = true;
+        let mut in_case_header = false;
+        while let Some(c) = self.chars.next() {
+            let ends_word = c.is_whitespace() || matches!(c, ';' | '&' | '|' | '(' | ')' | '<' | '>');
+            if command && ends_word && (!word.is_empty() || word_quoted) {
+                let reserved = if word_quoted { "" } else { word.as_str() };
+                match reserved {
+                    "case" if command_position => {
+                        cases.push(depth);
+                        in_case_header = true;
+                    }
+                    "esac" if command_position => {
+                        cases.pop();
+                    }
+                    "in" => in_case_header = false,
+                    _ => {}
                 }
-                depth += 1;
-                continue;
+                command_position = !in_case_header
+                    && matches!(
+                        reserved,
+                        "if" | "then" | "else" | "elif" | "do" | "while" | "until" | "{" | "!"
+                    );
+                word.clear();
+                word_quoted = false;
             }
-            if self.peek_matches(end) {
-                for _ in 0..end_len {
-                    self.chars.next();
+            if command && ends_word && !matches!(c, ' ' | '\t') {
+                command_position = true;
+            }
+            match c {
+                '\\' | '\'' | '"' | '`' if command => {
+                    buffer.push(c);
+                    word_quoted = true;
+                    if c == '\\' {
+                        if let Some(next) = self.chars.next() {
+                            buffer.push(next);
+                        }
+                    } else {
+                        self.copy_quoted(c, buffer)?;
+                    }
+                }
+                '#' if command && word.is_empty() && !word_quoted => {
+                    buffer.push(c);
+                    while let Some(&next) = self.chars.peek() {
+                        if next == '\n' {
+                            break;
+                        }
+                        buffer.push(next);
+                        self.chars.next();
+                    }
+                }
+                c if c == close && cases.last() == Some(&depth) => buffer.push(c),
+                c if c == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(());
+                    }
+                    buffer.push(c);
+                }
+                c if c == open => {
+                    depth += 1;
+                    buffer.push(c);
                 }
-                depth -= 1;
-                if depth == 0 {
+                c => {
+                    if command && !ends_word {
+                        word.push(c);
+                    }
+                    buffer.push(c);
+                }
+            }
+        }
+        Err(ShellError::Syntax(format!("unclosed {}", open)))
+    }
+
+    /// Copies quoted text up to and including the closing `quote`, which was
+    /// already copied. Backslashes escape the next character except in
+    /// single quotes.
+    fn copy_quoted(&mut self, quote: char, buffer: &mut String) -> Result<(), ShellError> {
+        while let Some(c) = self.chars.next() {
+            buffer.push(c);
+            if c == quote {
+                return Ok(());
+            }
+            if c == '\\'
+                && quote != '\''
+                && let Some(next) = self.chars.next()
+            {
+                buffer.push(next);
+            }
+        }
+        Err(ShellError::Syntax(format!("unclosed {}", quote)))
+    }
+
+    /// Reads the body of `((...))` up to the closing `))`, balancing inner parentheses.
+    fn read_arithmetic(&mut self, buffer: &mut String) -> Result<(), ShellError> {
+        let mut depth = 0;
+        while let Some(c) = self.chars.next() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    if let Some(')') = self.chars.peek() {
+                        self.chars.next();
+                        return Ok(());
+                    }
                     break;
                 }
-                buffer.push_str(end);
-                continue;
+                ')' => depth -= 1,
+                _ => {}
+            }
+            buffer.push(c);
+        }
+        Err(ShellError::Syntax("unclosed ((".to_string()))
+    }
+
+}
+
+/// The line that ends a here-document opened with `delimiter`, and whether
+/// any part of the delimiter was quoted, which turns off expansion in the body.
+pub fn heredoc_delimiter(delimiter: &Word) -> (String, bool) {
+    let mut end = String::new();
+    let mut quoted = false;
+    for part in &delimiter.parts {
+        match part {
+            WordPart::Literal((text, quote)) => {
+                end.push_str(text);
+                quoted |= *quote != QuoteType::None;
             }
-            if let Some(c) = self.chars.next() {
-                buffer.push(c);
+            WordPart::VariableSubstitution(var) => {
+                end.push('$');
+                end.push_str(var);
             }
+            WordPart::Quoted(part) => {
+                if let WordPart::VariableSubstitution(var) = part.as_ref() {
+                    end.push('$');
+                    end.push_str(var);
+                }
+                quoted = true;
+            }
+            _ => {}
         }
-        if depth == 0 {
-            Ok(())
-        } else {
-            Err(ShellError::Syntax(format!("unclosed {}", start)))
+    }
+    (end, quoted)
+}
+
+fn flush_literal(buffer: &mut (String, QuoteType), parts: &mut Vec<WordPart>) {
+    if !buffer.0.is_empty() {
+        parts.push(WordPart::Literal((std::mem::take(&mut buffer.0), buffer.1)));
+    }
+}
+
+/// Length of the parameter name at the start of `text`: an identifier,
+/// a positional number or one of the special parameters.
+fn parameter_name_len(text: &str) -> usize {
+    match text.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len()),
+        Some(c) if c.is_ascii_digit() => text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len()),
+        Some('?' | '#' | '@' | '*' | '$' | '!' | '-') => 1,
+        _ => 0,
+    }
+}
+
+/// Splits `pattern/replacement` at the first `/` that is not escaped or quoted.
+fn split_replacement(text: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote != Some('\'') => escaped = true,
+            '\'' | '"' if quote.is_none() => quote = Some(c),
+            _ if Some(c) == quote => quote = None,
+            '/' if quote.is_none() => return (&text[..i], &text[i + 1..]),
+            _ => {}
         }
     }
+    (text, "")
+}
 
-    fn peek_matches(&mut self, s: &str) -> bool {
-        let mut iter = self.chars.clone();
-        for expected_char in s.chars() {
-            match iter.next() {
-                Some(c) if c == expected_char => (),
-                _ => return false,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The command text of the first command substitution in `source`.
+    fn substitution(source: &str) -> String {
+        let tokens = Tokenizer::new(source).tokenize().unwrap();
+        let mut parts: Vec<WordPart> = tokens
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.parts),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        while let Some(part) = parts.pop() {
+            match part {
+                WordPart::CommandSubstitution { command, .. } => return command,
+                WordPart::Quoted(part) => parts.push(*part),
+                _ => {}
             }
         }
-        true
+        panic!("no command substitution in {source}")
+    }
+
+    #[test]
+    fn quoted_paren_does_not_close_a_substitution() {
+        assert_eq!(substitution("echo $(echo \")\")"), "echo \")\"");
+    }
+
+    #[test]
+    fn quoted_paren_inside_a_quoted_substitution() {
+        assert_eq!(substitution("\"$(echo \"a)b\")\""), "echo \"a)b\"");
+    }
+
+    #[test]
+    fn case_pattern_does_not_close_a_substitution() {
+        assert_eq!(
+            substitution("x=$(case a in a) echo c;; esac)"),
+            "case a in a) echo c;; esac"
+        );
     }
 }
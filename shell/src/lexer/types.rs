@@ -1,5 +1,3 @@
-use std::process::Command;
-use std::process::Stdio;
 #[derive(Debug, Clone, PartialEq)]
 pub struct Word {
     pub parts: Vec<WordPart>,
@@ -7,73 +5,57 @@ pub struct Word {
 }
 
 use crate::envirement::ShellEnv;
+use crate::error::ShellError;
+use crate::eval::eval_arith_text;
+use crate::expansion::{command_substitution, expand_parameter, process_substitution};
 
 impl Word {
+    /// Expands the word without touching the caller's environment, used when
+    /// a command is only displayed. Side effects like `$((i++))` are discarded
+    /// and substitutions are shown as written instead of being run again.
     pub fn expand(&self, env: &ShellEnv) -> String {
+        let mut scratch = env.clone();
+        self.parts.iter().map(|part| part.display(&mut scratch)).collect()
+    }
+
+    pub fn try_expand(&self, env: &mut ShellEnv) -> Result<String, ShellError> {
         let mut result = String::new();
         for part in &self.parts {
-            match part {
-                WordPart::CommandSubstitution(expression) => {
-                    let path = match env.get("0") {
-                        Some(val) => val,
-                        None => "".to_string(),
-                    };
-                    let command = match Command::new(path)
-                        .arg("-c")
-                        .arg(expression)
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::inherit())
-                        .spawn()
-                    {
-                        Ok(val) => val,
-                        Err(e) => {
-                            eprintln!("{e}");
-                            std::process::exit(1);
-                        }
-                    };
-                    let output = match command.wait_with_output() {
-                        Ok(output) => output,
-                        Err(e) => {
-                            eprintln!("Error running command: {}", e);
-                            return "".to_string();
-                        }
-                    };
-
-                    let output_str = match String::from_utf8(output.stdout) {
-                        Ok(val) => val,
-                        Err(e) => {
-                            eprintln!("Error converting output to string: {}", e);
-                            return "".to_string();
-                        }
-                    };
-                    result.push_str(&output_str.trim());
-
-                    // }
-                }
+            result.push_str(&part.try_expand(env)?);
+        }
+        Ok(result)
+    }
+}
 
-                WordPart::VariableSubstitution(var) => {
-                    if let Some(value) = env.get(&var) {
-                        result.push_str(&value);
-                    }
-                }
+impl WordPart {
+    pub fn try_expand(&self, env: &mut ShellEnv) -> Result<String, ShellError> {
+        match self {
+            WordPart::CommandSubstitution { command, .. } => command_substitution(command, env),
 
-                WordPart::ArithmeticSubstitution(word) => {
-                    result.push_str(&word);
-                }
-                WordPart::Literal(word) => match word.1 {
-                    QuoteType::Double => {
-                        result.push_str(&word.0);
-                    }
-                    QuoteType::Single => {
-                        result.push_str(&word.0);
-                    }
-                    QuoteType::None => {
-                        result.push_str(&word.0);
-                    }
-                },
+            WordPart::VariableSubstitution(var) => Ok(env.get(var).unwrap_or_default()),
+
+            WordPart::ParameterExpansion(expansion) => expand_parameter(expansion, env),
+
+            WordPart::ArithmeticSubstitution(expr) => {
+                Ok(eval_arith_text(expr, env)?.to_string())
+            }
+            WordPart::Quoted(part) => part.try_expand(env),
+            WordPart::ProcessSubstitution { command, output } => {
+                process_substitution(command, *output, env)
+            }
+            WordPart::Literal(word) => Ok(word.0.clone()),
+        }
+    }
+
+    fn display(&self, env: &mut ShellEnv) -> String {
+        match self {
+            WordPart::CommandSubstitution { command, .. } => format!("$({})", command),
+            WordPart::ProcessSubstitution { command, output } => {
+                format!("{}({})", if *output { '>' } else { '<' }, command)
             }
+            WordPart::Quoted(part) => part.display(env),
+            _ => self.try_expand(env).unwrap_or_default(),
         }
-        return result;
     }
 }
 
@@ -87,8 +69,13 @@ pub enum Token {
     RedirectInFd(u64),
     RedirectOutFd(u64),
     RedirectAppendFd(u64),
-    RedirectHereDoc,
+    RedirectHereDoc { fd: Option<u64>, strip_tabs: bool }, // << or <<-
+    // Takes the place of the delimiter word once the body has been read
+    HereDoc { delimiter: Word, body: Word },
+    RedirectHereString(Option<u64>), // <<<
+    RedirectReadWrite(Option<u64>),  // <>
     Semicolon,
+    DoubleSemicolon,
     Ampersand,
     LogicalAnd,
     LogicalOr,
@@ -99,6 +86,7 @@ pub enum Token {
     CloseParen,
     OpenBracket,
     CloseBracket,
+    Arithmetic(String), // (( expr ))
     Newline,
     Eof,
 }
@@ -108,14 +96,111 @@ pub enum QuoteType {
     Single,
     Double,
     None,
+    /// One character quoted with a backslash, `\c`
+    Escaped,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum WordPart {
     Literal((String, QuoteType)),
     VariableSubstitution(String),   // $USER
+    ParameterExpansion(ParameterExpansion), // ${USER:-nobody}
     ArithmeticSubstitution(String), // $((1 + 2))
-    CommandSubstitution(String),    // $(whoami)
+    CommandSubstitution { command: String, backtick: bool }, // $(whoami) or `whoami`
+    Quoted(Box<WordPart>),          // "$USER", never split or globbed
+    ProcessSubstitution { command: String, output: bool }, // <(sort a) or >(gzip)
+}
+
+/// A `${...}` expansion that applies an operator to the parameter's value.
+/// A plain `${name}` is kept as a `WordPart::VariableSubstitution`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterExpansion {
+    pub name: String,
+    pub op: ParameterOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterOp {
+    /// `${#name}`
+    Length,
+    /// `${name-word}`, with `colon` for `${name:-word}` which also treats empty as unset
+    UseDefault { word: Word, colon: bool },
+    /// `${name=word}` / `${name:=word}`
+    AssignDefault { word: Word, colon: bool },
+    /// `${name?word}` / `${name:?word}`
+    ErrorIfUnset { word: Word, colon: bool },
+    /// `${name+word}` / `${name:+word}`
+    UseAlternative { word: Word, colon: bool },
+    /// `${name#pattern}` / `${name##pattern}`
+    RemovePrefix { pattern: Word, longest: bool },
+    /// `${name%pattern}` / `${name%%pattern}`
+    RemoveSuffix { pattern: Word, longest: bool },
+    /// `${name/pattern/replacement}` and its `//`, `/#` and `/%` forms
+    Replace {
+        pattern: Word,
+        replacement: Word,
+        mode: ReplaceMode,
+    },
+    /// `${name[n]}`, `${name[@]}` or `${name[*]}`
+    Subscript(Subscript),
+}
+
+/// Which elements of a list variable such as `PIPESTATUS` a subscript takes.
+/// Any other variable is a list of its one value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subscript {
+    /// `[n]`, counting from 0
+    Index(usize),
+    /// `[@]`, every element joined with spaces
+    All,
+    /// `[*]`, every element joined with the first character of `IFS`
+    Joined,
+}
+
+/// Which match of the pattern `${name/pattern/replacement}` replaces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaceMode {
+    /// `/`, the first match
+    First,
+    /// `//`, every match
+    All,
+    /// `/#`, a match at the start of the value
+    Prefix,
+    /// `/%`, a match at the end of the value
+    Suffix,
+}
+
+/// Where a token sits in the source: byte offsets and the 1-based line
+/// and column of its first character.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// Fills in `line` and `column` of spans whose offsets are already set,
+    /// scanning the source once as long as they come in order.
+    pub fn locate_all(source: &str, spans: &mut [Span]) {
+        let (mut scanned, mut line, mut line_start) = (0, 1, 0);
+        for span in spans {
+            let start = span.start.min(source.len());
+            if start < scanned {
+                (scanned, line, line_start) = (0, 1, 0);
+            }
+            for (i, c) in source[scanned..start].char_indices() {
+                if c == '\n' {
+                    line += 1;
+                    line_start = scanned + i + 1;
+                }
+            }
+            scanned = start;
+            span.line = line;
+            span.column = source[line_start..start].chars().count() + 1;
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -15,10 +15,15 @@ pub mod commands {
     pub mod fg;
     pub mod jobs;
     pub mod kill;
+    pub mod lett;
+    pub mod local;
     pub mod mkdir;
     pub mod mv;
     pub mod pwd;
+    pub mod read;
     pub mod rm;
+    pub mod set;
+    pub mod shift;
     pub mod test;
     pub mod tru;
     pub mod typ;
@@ -29,6 +34,7 @@ use std::path::PathBuf;
 
 pub mod shell_interactions {
     pub mod buffer;
+    pub mod continuation;
     pub mod history_handler;
     pub mod rerender;
     pub mod utils;
@@ -44,6 +50,9 @@ pub mod executorr;
 pub mod executor;
 pub mod expansion;
 pub mod lexer;
+pub mod pattern;
+pub mod glob;
+pub mod brace;
 
 pub trait ShellCommand {
     fn execute(&self, env: &mut v::ShellEnv) -> Result<i32, ShellError>;
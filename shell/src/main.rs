@@ -1,24 +1,83 @@
 use nix::sys::signal::{signal, SigHandler, Signal};
 pub use shell::parser;
-use shell::events_handler::{self, ShellMode};
+use shell::events_handler::{self, FrontEnd, ShellMode};
+use std::io::Read;
 
 fn main() {
     unsafe {
     signal(Signal::SIGINT, SigHandler::SigIgn).unwrap(); // Ignore Ctrl+C in shell
     }
     let args: Vec<String> = std::env::args().collect();
-    let mode = if let Some(pos) = args.iter().position(|arg| arg == "-c") {
-        if let Some(cmd) = args.get(pos + 1) {
-            ShellMode::Command(cmd.clone())
-        } else {
-            eprintln!("error: -c needs a command string");
+    let shell_name = args.first().cloned().unwrap_or_default();
+
+    // options come before the script operand
+    let mut front_end = None;
+    let mut write = false;
+    let mut command = None;
+    let mut index = 1;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "-n" => front_end = Some(FrontEnd::Check),
+            "--dump-tokens" => front_end = Some(FrontEnd::DumpTokens),
+            "--dump-ast" => front_end = Some(FrontEnd::DumpAst),
+            "--fmt" => front_end = Some(FrontEnd::Format),
+            "-w" => write = true,
+            "-c" => {
+                index += 1;
+                match args.get(index) {
+                    Some(cmd) => command = Some(cmd.clone()),
+                    None => {
+                        eprintln!("error: -c needs a command string");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            _ => break,
+        }
+        index += 1;
+    }
+    let operands = &args[index..];
+    if write {
+        if front_end != Some(FrontEnd::Format) || command.is_some() || operands.is_empty() {
+            eprintln!("error: -w rewrites a script file given to --fmt");
             std::process::exit(1);
         }
-    } else if atty::is(atty::Stream::Stdin) {
-        ShellMode::Interactive
-    } else {
-        ShellMode::NonInteractive
+        front_end = Some(FrontEnd::FormatInPlace);
+    }
+
+    let mode = match (front_end, command) {
+        (Some(action), Some(cmd)) => ShellMode::FrontEnd(action, cmd, shell_name),
+        (Some(action), None) => {
+            let (source, name) = match operands.first() {
+                Some(path) => (std::fs::read_to_string(path), path.clone()),
+                None => {
+                    let mut source = String::new();
+                    let read = std::io::stdin().read_to_string(&mut source);
+                    (read.map(|_| source), shell_name)
+                }
+            };
+            match source {
+                Ok(source) => ShellMode::FrontEnd(action, source, name),
+                Err(e) => {
+                    eprintln!("{}: {}", name, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        (None, Some(cmd)) => ShellMode::Command(cmd),
+        (None, None) if !operands.is_empty() => ShellMode::Script(operands[0].clone()),
+        (None, None) if atty::is(atty::Stream::Stdin) => ShellMode::Interactive,
+        (None, None) => ShellMode::NonInteractive,
     };
 
-    events_handler::Shell::new(mode).run();
+    // `-c cmd name args...` and `script args...` both take `$0` from the
+    // first operand and the positional parameters from the rest
+    let mut shell = events_handler::Shell::new(mode);
+    if let Some((name, params)) = operands.split_first() {
+        shell.set_arguments(Some(name.clone()), params.to_vec());
+    }
+    let status = shell.run();
+    // dropping the shell puts the terminal back out of raw mode
+    drop(shell);
+    std::process::exit(status);
 }
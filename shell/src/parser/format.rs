@@ -0,0 +1,634 @@
+use crate::lexer::tokenize::heredoc_delimiter;
+use crate::lexer::types::{ParameterExpansion, ParameterOp, QuoteType, ReplaceMode, Subscript, Word, WordPart};
+use crate::parser::types::*;
+
+/// Writes an AST back out as shell source, either on one line or in the
+/// canonical layout used by `push --fmt`.
+struct ScriptWriter {
+    out: String,
+    indent: usize,
+    // one line, as shown by `jobs`, instead of one command per line
+    inline: bool,
+    at_line_start: bool,
+    // here-document bodies that follow the line being written
+    heredocs: Vec<String>,
+}
+
+impl ScriptWriter {
+    fn new(inline: bool) -> Self {
+        Self {
+            out: String::new(),
+            indent: 0,
+            inline,
+            at_line_start: false,
+            heredocs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.at_line_start {
+            self.out.push_str(&"  ".repeat(self.indent));
+            self.at_line_start = false;
+        }
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        if self.inline {
+            self.out.push(' ');
+            return;
+        }
+        self.out.push('\n');
+        for body in self.heredocs.drain(..) {
+            self.out.push_str(&body);
+        }
+        self.at_line_start = true;
+    }
+
+    /// Ends a command in a list: `;` on one line, a line break otherwise.
+    fn end_command(&mut self) {
+        if self.inline && !self.out.ends_with('&') {
+            self.out.push(';');
+        }
+        self.newline();
+    }
+
+    /// Writes `node` one level deeper, starting on a new line.
+    fn block(&mut self, node: &AstNode) {
+        self.indent += 1;
+        self.newline();
+        self.node(node);
+        self.indent -= 1;
+    }
+
+    /// Writes a list, a trailing comment stays on the line of the command
+    /// before it. One line has no room for comments.
+    fn sequence(&mut self, nodes: &[AstNode]) {
+        let mut first = true;
+        for node in nodes {
+            match node {
+                AstNode::Comment { .. } if self.inline => continue,
+                AstNode::Comment { trailing: true, .. } if !first => self.push(" "),
+                _ if !first => self.end_command(),
+                _ => {}
+            }
+            self.node(node);
+            first = false;
+        }
+    }
+
+    /// Writes the `then` or `do` after a condition, on a line of its own when
+    /// a comment ends the condition.
+    fn keyword_after(&mut self, condition: &AstNode, keyword: &str) {
+        let comment = match condition {
+            AstNode::Sequence(nodes) => nodes.last(),
+            node => Some(node),
+        };
+        if matches!(comment, Some(AstNode::Comment { .. })) && !self.inline {
+            self.newline();
+        } else {
+            self.push("; ");
+        }
+        self.push(keyword);
+    }
+
+    fn redirects(&mut self, redirects: &[Redirect]) {
+        for redirect in redirects {
+            self.push(" ");
+            self.push(&redirect.to_text());
+            if let (false, RedirectOp::HereDoc { delimiter, .. }) = (self.inline, &redirect.kind) {
+                let (end, quoted) = heredoc_delimiter(delimiter);
+                let body = if quoted {
+                    literal_text(&redirect.target)
+                } else {
+                    double_quoted_text(&redirect.target.parts, true)
+                };
+                self.heredocs.push(format!("{}{}\n", body, end));
+            }
+        }
+    }
+
+    fn node(&mut self, node: &AstNode) {
+        match node {
+            AstNode::Command {
+                cmd,
+                args,
+                assignments,
+                redirects,
+            } => {
+                let mut words: Vec<String> = assignments
+                    .iter()
+                    .map(|(name, value)| format!("{}={}", name, value.to_text()))
+                    .collect();
+                if !cmd.parts.is_empty() {
+                    words.push(cmd.to_text());
+                }
+                words.extend(args.iter().map(Word::to_text));
+                self.push(&words.join(" "));
+                self.redirects(redirects);
+            }
+
+            AstNode::Pipeline(nodes) => {
+                for (i, node) in nodes.iter().enumerate() {
+                    if i > 0 {
+                        self.push(" | ");
+                    }
+                    self.node(node);
+                }
+            }
+
+            AstNode::Sequence(nodes) => self.sequence(nodes),
+
+            AstNode::And(lhs, rhs) => {
+                self.node(lhs);
+                self.push(" && ");
+                self.node(rhs);
+            }
+
+            AstNode::Or(lhs, rhs) => {
+                self.node(lhs);
+                self.push(" || ");
+                self.node(rhs);
+            }
+
+            AstNode::Not(node) => {
+                self.push("! ");
+                self.node(node);
+            }
+
+            AstNode::Background(node) => {
+                self.node(node);
+                self.push(" &");
+            }
+
+            AstNode::Subshell { body, redirects } => {
+                self.push("(");
+                self.block(body);
+                self.newline();
+                self.push(")");
+                self.redirects(redirects);
+            }
+
+            AstNode::Group { commands, redirects } => {
+                self.push("{");
+                self.indent += 1;
+                self.newline();
+                self.sequence(commands);
+                self.indent -= 1;
+                self.end_command();
+                self.push("}");
+                self.redirects(redirects);
+            }
+
+            AstNode::If {
+                condition,
+                then_branch,
+                elif,
+                else_branch,
+                redirects,
+            } => {
+                self.push("if ");
+                self.node(condition);
+                self.keyword_after(condition, "then");
+                self.block(then_branch);
+                for (condition, body) in elif {
+                    self.end_command();
+                    self.push("elif ");
+                    self.node(condition);
+                    self.keyword_after(condition, "then");
+                    self.block(body);
+                }
+                if let Some(body) = else_branch {
+                    self.end_command();
+                    self.push("else");
+                    self.block(body);
+                }
+                self.end_command();
+                self.push("fi");
+                self.redirects(redirects);
+            }
+
+            AstNode::While {
+                condition,
+                body,
+                redirects,
+            }
+            | AstNode::Until {
+                condition,
+                body,
+                redirects,
+            } => {
+                let keyword = if matches!(node, AstNode::While { .. }) {
+                    "while "
+                } else {
+                    "until "
+                };
+                self.push(keyword);
+                self.node(condition);
+                self.keyword_after(condition, "do");
+                self.block(body);
+                self.end_command();
+                self.push("done");
+                self.redirects(redirects);
+            }
+
+            AstNode::For {
+                var,
+                values,
+                body,
+                redirects,
+            } => {
+                self.push("for ");
+                self.push(var);
+                self.push(" in");
+                for value in values {
+                    self.push(" ");
+                    self.push(&value.to_text());
+                }
+                self.push("; do");
+                self.block(body);
+                self.end_command();
+                self.push("done");
+                self.redirects(redirects);
+            }
+
+            AstNode::Case {
+                word,
+                arms,
+                redirects,
+            } => {
+                self.push("case ");
+                self.push(&word.to_text());
+                self.push(" in");
+                self.indent += 1;
+                for (patterns, body) in arms {
+                    self.newline();
+                    let patterns: Vec<String> = patterns.iter().map(Word::to_text).collect();
+                    self.push(&patterns.join(" | "));
+                    self.push(")");
+                    if matches!(body, AstNode::Sequence(nodes) if nodes.is_empty()) {
+                        self.push(" ;;");
+                        continue;
+                    }
+                    self.block(body);
+                    self.indent += 1;
+                    self.newline();
+                    self.push(";;");
+                    self.indent -= 1;
+                }
+                self.indent -= 1;
+                self.newline();
+                self.push("esac");
+                self.redirects(redirects);
+            }
+
+            AstNode::FunctionDef { name, body } => {
+                self.push(&name.to_text());
+                self.push("() ");
+                self.node(body);
+            }
+
+            AstNode::Break(count) | AstNode::Continue(count) | AstNode::Return(count) => {
+                self.push(match node {
+                    AstNode::Break(_) => "break",
+                    AstNode::Continue(_) => "continue",
+                    _ => "return",
+                });
+                if let Some(count) = count {
+                    self.push(" ");
+                    self.push(&count.to_text());
+                }
+            }
+
+            AstNode::ArithmeticCommand(expr) => self.push(&format!("(({}))", expr)),
+
+            AstNode::Comment { text, .. } => {
+                if !self.inline {
+                    self.push(text);
+                }
+            }
+        }
+    }
+}
+
+impl AstNode {
+    /// The node as source on a single line, here-document bodies left out.
+    pub fn to_text(&self) -> String {
+        let mut writer = ScriptWriter::new(true);
+        writer.node(self);
+        writer.out
+    }
+
+    /// The node as a script with one command per line and two spaces of
+    /// indentation per level. Formatting the output again gives it back.
+    pub fn format(&self) -> String {
+        let mut writer = ScriptWriter::new(false);
+        writer.node(self);
+        writer.newline();
+        writer.out
+    }
+}
+
+impl Redirect {
+    /// The redirection as written, a here-document shows only its delimiter.
+    pub fn to_text(&self) -> String {
+        let fd_str = self.fd.map(|fd| fd.to_string()).unwrap_or_default();
+        let op_str = match self.kind {
+            RedirectOp::Write => ">",
+            RedirectOp::Append => ">>",
+            RedirectOp::Read => "<",
+            RedirectOp::HereDoc {
+                strip_tabs: false, ..
+            } => "<<",
+            RedirectOp::HereDoc {
+                strip_tabs: true, ..
+            } => "<<-",
+            RedirectOp::ReadWrite => "<>",
+            RedirectOp::HereString => "<<<",
+        };
+        let target = match &self.kind {
+            RedirectOp::HereDoc { delimiter, .. } => delimiter,
+            _ => &self.target,
+        };
+        format!("{}{}{}", fd_str, op_str, target.to_text())
+    }
+}
+
+impl Word {
+    /// The word with the quoting needed to read it back unchanged. Runs of
+    /// double-quoted parts share one pair of quotes.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let mut start = 0;
+        while start < self.parts.len() {
+            let end = start
+                + self.parts[start..]
+                    .iter()
+                    .take_while(|part| is_double_quoted(part))
+                    .count();
+            if end > start {
+                out.push('"');
+                out.push_str(&double_quoted_text(&self.parts[start..end], false));
+                out.push('"');
+                start = end;
+                continue;
+            }
+            match &self.parts[start] {
+                WordPart::Literal((text, QuoteType::None)) => out.push_str(text),
+                WordPart::Literal((text, QuoteType::Escaped)) => {
+                    out.push('\\');
+                    out.push_str(text);
+                }
+                WordPart::Literal((text, _)) => {
+                    out.push('\'');
+                    out.push_str(text);
+                    out.push('\'');
+                }
+                part => out.push_str(&dollar_text(part, self.parts.get(start + 1), false)),
+            }
+            start += 1;
+        }
+        out
+    }
+}
+
+fn is_double_quoted(part: &WordPart) -> bool {
+    matches!(
+        part,
+        WordPart::Quoted(_) | WordPart::Literal((_, QuoteType::Double))
+    )
+}
+
+/// The inside of a double-quoted string, or of an unquoted here-document
+/// body where `"` needs no backslash.
+fn double_quoted_text(parts: &[WordPart], heredoc: bool) -> String {
+    let mut out = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        match part {
+            WordPart::Literal((text, _)) => {
+                let mut chars = text.chars().peekable();
+                while let Some(c) = chars.next() {
+                    let escape = match c {
+                        '$' | '`' => true,
+                        '"' => !heredoc,
+                        // a backslash only needs doubling where it would escape
+                        '\\' => matches!(chars.peek(), None | Some('$' | '`' | '\\' | '"' | '\n')),
+                        _ => false,
+                    };
+                    if escape {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+            }
+            part => out.push_str(&dollar_text(part, parts.get(i + 1), true)),
+        }
+    }
+    out
+}
+
+fn literal_text(word: &Word) -> String {
+    word.parts
+        .iter()
+        .filter_map(|part| match part {
+            WordPart::Literal((text, _)) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// An expansion part as written. `next` is the part that follows, a name
+/// that would run into it gets braces.
+fn dollar_text(part: &WordPart, next: Option<&WordPart>, quoted: bool) -> String {
+    match part {
+        WordPart::VariableSubstitution(name) => {
+            let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+            let runs_on = match next {
+                Some(WordPart::Literal((text, quote))) => {
+                    (*quote == QuoteType::Double) == quoted
+                        && !matches!(quote, QuoteType::Single | QuoteType::Escaped)
+                        && text.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+                }
+                _ => false,
+            };
+            if (is_identifier && runs_on) || (name.len() > 1 && !is_identifier) {
+                format!("${{{}}}", name)
+            } else {
+                format!("${}", name)
+            }
+        }
+        WordPart::ParameterExpansion(expansion) => parameter_text(expansion),
+        WordPart::ArithmeticSubstitution(expr) => format!("$(({}))", expr),
+        WordPart::CommandSubstitution {
+            command,
+            backtick: false,
+        } => format!("$({})", command),
+        WordPart::CommandSubstitution {
+            command,
+            backtick: true,
+        } => backtick_text(command, quoted),
+        WordPart::ProcessSubstitution { command, output } => {
+            format!("{}({})", if *output { '>' } else { '<' }, command)
+        }
+        WordPart::Quoted(part) => dollar_text(part, next, quoted),
+        WordPart::Literal((text, _)) => text.clone(),
+    }
+}
+
+/// A `` `command` `` substitution with the backslashes the tokenizer took
+/// out put back.
+fn backtick_text(command: &str, quoted: bool) -> String {
+    let mut out = String::from("`");
+    let mut chars = command.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escape = match c {
+            '`' => true,
+            '"' => quoted,
+            '\\' => match chars.peek() {
+                None | Some('$' | '`' | '\\') => true,
+                Some('"') => quoted,
+                _ => false,
+            },
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('`');
+    out
+}
+
+fn parameter_text(expansion: &ParameterExpansion) -> String {
+    let name = &expansion.name;
+    let colon = |colon: bool| if colon { ":" } else { "" };
+    match &expansion.op {
+        ParameterOp::Length => format!("${{#{}}}", name),
+        ParameterOp::UseDefault { word, colon: c } => {
+            format!("${{{}{}-{}}}", name, colon(*c), word.to_text())
+        }
+        ParameterOp::AssignDefault { word, colon: c } => {
+            format!("${{{}{}={}}}", name, colon(*c), word.to_text())
+        }
+        ParameterOp::ErrorIfUnset { word, colon: c } => {
+            format!("${{{}{}?{}}}", name, colon(*c), word.to_text())
+        }
+        ParameterOp::UseAlternative { word, colon: c } => {
+            format!("${{{}{}+{}}}", name, colon(*c), word.to_text())
+        }
+        ParameterOp::RemovePrefix { pattern, longest } => {
+            let op = if *longest { "##" } else { "#" };
+            format!("${{{}{}{}}}", name, op, pattern.to_text())
+        }
+        ParameterOp::RemoveSuffix { pattern, longest } => {
+            let op = if *longest { "%%" } else { "%" };
+            format!("${{{}{}{}}}", name, op, pattern.to_text())
+        }
+        ParameterOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        } => {
+            let op = match mode {
+                ReplaceMode::First => "/",
+                ReplaceMode::All => "//",
+                ReplaceMode::Prefix => "/#",
+                ReplaceMode::Suffix => "/%",
+            };
+            format!(
+                "${{{}{}{}/{}}}",
+                name,
+                op,
+                pattern.to_text(),
+                replacement.to_text()
+            )
+        }
+        ParameterOp::Subscript(subscript) => match subscript {
+            Subscript::Index(index) => format!("${{{}[{}]}}", name, index),
+            Subscript::All => format!("${{{}[@]}}", name),
+            Subscript::Joined => format!("${{{}[*]}}", name),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_source_with_comments;
+
+    fn format(source: &str) -> String {
+        parse_source_with_comments(source)
+            .unwrap()
+            .map(|tree| tree.format())
+            .unwrap_or_default()
+    }
+
+    const SCRIPTS: &[&str] = &[
+        "a=1 b=\"x y\" cmd arg >out 2>&1 </dev/null",
+        "if a; then b; elif c; then d; else e; fi",
+        "for i in 1 2 3; do echo $i; done; while false; do :; done; until true; do :; done",
+        "case $x in a|b) echo ab;; *) echo other;; esac",
+        "f() { local v=$1; return 2; } >log; function g ( echo sub )",
+        "a | b && c || ! d & (e; f) | { g; h; }",
+        "echo ${v:-d} ${v#p} ${v%%s} ${v/#a/b} ${v//a/b} ${#v} ${v[0]} ${v[@]} ${v[*]}",
+        "echo $(cmd \"a b\") $((1 + $(c))) <(sort a) >(gzip) `old`",
+        "cat <<EOF\nbody $x\nEOF\ncat <<-'END'\n\tliteral $x\n\tEND",
+        "echo 'it'\\''s' 'a'b \"x\"'y' \\a 'c' '' \"$a\"b ${a}b $a\\b",
+        "(( i += 2 )); echo \"$(( i * 3 ))\"",
+        "#!/bin/sh\n# setup\na=1 # trailing\nif a # why\nthen # now\n  b\n  # done\nfi",
+        "f() {\n  # body\n  g; # after\n}\ncase $x in\n  # first\n  a) ;;\n  b) c ;; # b\n  # last\nesac\n# end",
+        "while a # w\ndo b; done; echo `cmd \\`nested\\` \\$x` \"`a \\\"b\\\"`\"",
+    ];
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for script in SCRIPTS {
+            let once = format(script);
+            assert_eq!(format(&once), once, "script: {}", script);
+        }
+    }
+
+    #[test]
+    fn quoting_is_kept_as_written() {
+        assert_eq!(
+            format("echo 'it'\\''s' 'a'b \\a 'c' ''"),
+            "echo 'it'\\''s' 'a'b \\a 'c' ''\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept_in_place() {
+        assert_eq!(
+            format("#!/bin/sh\n# one\na # two\n{ b; # three\n}\n# four"),
+            "#!/bin/sh\n# one\na # two\n{\n  b # three\n}\n# four\n"
+        );
+        assert_eq!(
+            format("if a # why\nthen b; fi"),
+            "if a # why\nthen\n  b\nfi\n"
+        );
+    }
+
+    #[test]
+    fn empty_case_arm_stays_on_its_pattern_line() {
+        assert_eq!(
+            format("case $x in a) ;; *) b;; esac"),
+            "case $x in\n  a) ;;\n  *)\n    b\n    ;;\nesac\n"
+        );
+    }
+
+    #[test]
+    fn backticks_are_kept_as_written() {
+        assert_eq!(
+            format("echo `a \\`b\\` \\\\` \"`c \\\"d\\\"`\""),
+            "echo `a \\`b\\` \\\\` \"`c \\\"d\\\"`\"\n"
+        );
+    }
+
+    #[test]
+    fn compound_commands_are_indented() {
+        assert_eq!(
+            format("if a; then for i in 1; do b; done; fi"),
+            "if a; then\n  for i in 1; do\n    b\n  done\nfi\n"
+        );
+    }
+}
@@ -1,4 +1,7 @@
+pub mod format;
+pub mod parse_arithmetic;
 pub mod parse_assignment;
+pub mod parse_case;
 pub mod parse_command;
 pub mod parse_command_or_if;
 pub mod parse_flow_control;
@@ -10,21 +13,76 @@ pub mod parse_op;
 pub mod parse_pipeline;
 pub mod parse_redirection;
 pub mod parse_sequence;
+pub mod parse_subshell;
 pub mod parse_while_or_until;
 pub mod types;
 
 use crate::error::ShellError;
-use crate::lexer::types::{QuoteType, Token, WordPart};
+use crate::lexer::tokenize::Tokenizer;
+use crate::lexer::types::{QuoteType, Span, Token, WordPart};
 use crate::parser::types::*;
 
 pub struct Parser {
     pub tokens: Vec<Token>,
     pub pos: usize,
+    // One per token when known, used to point errors at their token
+    pub spans: Vec<Span>,
+    // Comments not yet placed in the tree, in source order
+    pub comments: Vec<(Span, String)>,
+}
+
+/// Tokenizes and parses `source`, errors carry the span of the token where
+/// they were found.
+pub fn parse_source(source: &str) -> Result<Option<AstNode>, ShellError> {
+    let mut tokenizer = Tokenizer::new(source);
+    let tokens = tokenizer.tokenize()?;
+    Parser::with_spans(tokens, std::mem::take(&mut tokenizer.spans)).parse()
+}
+
+/// Like `parse_source`, but the comments are kept as `AstNode::Comment`s in
+/// the lists they appear in, so `--fmt` can write them back.
+pub fn parse_source_with_comments(source: &str) -> Result<Option<AstNode>, ShellError> {
+    let mut tokenizer = Tokenizer::new(source);
+    let tokens = tokenizer.tokenize()?;
+    let mut parser = Parser::with_spans(tokens, std::mem::take(&mut tokenizer.spans));
+    parser.comments = tokenizer
+        .comments
+        .iter()
+        .map(|span| (*span, source[span.start..span.end].to_string()))
+        .collect();
+    parser.parse()
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self::with_spans(tokens, Vec::new())
+    }
+
+    pub fn with_spans(tokens: Vec<Token>, spans: Vec<Span>) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            spans,
+            comments: Vec::new(),
+        }
+    }
+
+    /// Moves the comments found before the current token into `list`. One
+    /// on the same line as the token before it trails that command.
+    pub fn take_comments(&mut self, list: &mut Vec<AstNode>) {
+        let before = self.spans.get(self.pos).map_or(usize::MAX, |span| span.start);
+        let count = self
+            .comments
+            .iter()
+            .take_while(|(span, _)| span.start < before)
+            .count();
+        for (span, text) in self.comments.drain(..count) {
+            let previous = self.spans.iter().take_while(|token| token.start < span.start).last();
+            list.push(AstNode::Comment {
+                text,
+                trailing: previous.is_some_and(|token| token.line == span.line),
+            });
+        }
     }
 
     pub fn look_ahead(&self, offset: usize) -> Option<&Token> {
@@ -73,6 +131,7 @@ impl Parser {
                                 || part.0 == "elif"
                                 || part.0 == "do"
                                 || part.0 == "done"
+                                || part.0 == "esac"
                                 || part.0 == "in")
                                 && part.1 == QuoteType::None
                             {
@@ -93,6 +152,17 @@ impl Parser {
         }
     }
 
+    /// Whether the current token is the unquoted word `expected`.
+    pub fn is_word(&self, expected: &str) -> bool {
+        match self.current() {
+            Some(Token::Word(word)) if word.parts.len() == 1 => matches!(
+                &word.parts[0],
+                WordPart::Literal(s) if s.0 == expected && s.1 == QuoteType::None
+            ),
+            _ => false,
+        }
+    }
+
     pub fn expect_word(&mut self, expected: &str) -> Result<(), ShellError> {
         match self.current() {
             Some(Token::Word(word)) => {
@@ -110,6 +180,12 @@ impl Parser {
         Err(ShellError::Syntax(format!("Expected '{}'", expected)))
     }
 
+    pub fn skip_newlines(&mut self) {
+        while let Some(Token::Newline) = self.current() {
+            self.advance();
+        }
+    }
+
     pub fn expect_delimiter(&mut self) -> bool {
         match self.current() {
             Some(Token::Semicolon) | Some(Token::Newline) | Some(Token::Ampersand) => {
@@ -124,16 +200,23 @@ impl Parser {
         match self.parse_sequence(false) {
             Ok(ast) => {
                 if !self.is_eof() {
-                    return Err(ShellError::Parse(format!(
-                        "unexpected token at end of tokens{:#?}",
-                        self.current()
-                    )));
+                    return Err(self.locate(ShellError::Parse("unexpected token".to_string())));
                 }
                 return Ok(ast);
             }
             Err(e) => {
-                return Err(e);
+                return Err(self.locate(e));
+            }
+        }
+    }
+
+    /// Attaches the span of the current token to a syntax or parse error.
+    fn locate(&self, error: ShellError) -> ShellError {
+        match (&error, self.spans.get(self.pos)) {
+            (ShellError::Syntax(_) | ShellError::Parse(_), Some(span)) => {
+                ShellError::Located(Box::new(error), *span)
             }
+            _ => error,
         }
     }
 }
@@ -0,0 +1,417 @@
+use crate::error::ShellError;
+use crate::parser::types::{ArithmeticExpr, BinaryOperator, UnaryOperator};
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArithToken {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+    OpenParen,
+    CloseParen,
+}
+
+// Longest operators first so `**=` wins over `**` and `*`
+const OPERATORS: [&str; 38] = [
+    "<<=", ">>=", "**=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~",
+    "&", "^", "|", "?", ":", ",",
+];
+
+/// Parses the text of `$(( ... ))` / `(( ... ))` into an `ArithmeticExpr`.
+pub struct ArithmeticParser {
+    tokens: Vec<ArithToken>,
+    pos: usize,
+}
+
+impl ArithmeticParser {
+    pub fn new(input: &str) -> Result<Self, ShellError> {
+        Ok(Self {
+            tokens: tokenize_arithmetic(input)?,
+            pos: 0,
+        })
+    }
+
+    /// Parses the whole input. Empty input yields `None`.
+    pub fn parse(&mut self) -> Result<Option<ArithmeticExpr>, ShellError> {
+        if self.tokens.is_empty() {
+            return Ok(None);
+        }
+        let expr = self.parse_comma()?;
+        if let Some(token) = self.current() {
+            return Err(syntax_error(&format!("unexpected token {}", describe(token))));
+        }
+        Ok(Some(expr))
+    }
+
+    fn current(&self) -> Option<&ArithToken> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) {
+        self.pos += 1;
+    }
+
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.current(), Some(ArithToken::Op(current)) if *current == op) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.current() {
+            Some(ArithToken::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let mut lhs = self.parse_assignment()?;
+        while self.eat_op(",") {
+            let rhs = self.parse_assignment()?;
+            lhs = binary(BinaryOperator::Comma, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_assignment(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let lhs = self.parse_ternary()?;
+
+        let op = match self.peek_op() {
+            Some(op) => op,
+            None => return Ok(lhs),
+        };
+        let compound = match op {
+            "=" => None,
+            "+=" => Some(BinaryOperator::Add),
+            "-=" => Some(BinaryOperator::Sub),
+            "*=" => Some(BinaryOperator::Mul),
+            "/=" => Some(BinaryOperator::Div),
+            "%=" => Some(BinaryOperator::Mod),
+            "**=" => Some(BinaryOperator::Pow),
+            "<<=" => Some(BinaryOperator::ShiftLeft),
+            ">>=" => Some(BinaryOperator::ShiftRight),
+            "&=" => Some(BinaryOperator::BitAnd),
+            "^=" => Some(BinaryOperator::BitXor),
+            "|=" => Some(BinaryOperator::BitOr),
+            _ => return Ok(lhs),
+        };
+
+        let var = match lhs {
+            ArithmeticExpr::Variable(name) => name,
+            _ => return Err(syntax_error(&format!("attempted assignment to non-variable ({})", op))),
+        };
+        self.advance();
+
+        // assignment is right associative
+        let value = Box::new(self.parse_assignment()?);
+        Ok(match compound {
+            None => ArithmeticExpr::Assignment { var, value },
+            Some(op) => ArithmeticExpr::CompoundAssignment { var, op, value },
+        })
+    }
+
+    fn parse_ternary(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let condition = self.parse_binary(0)?;
+        if !self.eat_op("?") {
+            return Ok(condition);
+        }
+        let then_expr = self.parse_comma()?;
+        if !self.eat_op(":") {
+            return Err(syntax_error("expected `:' in conditional expression"));
+        }
+        let else_expr = self.parse_assignment()?;
+        Ok(ArithmeticExpr::Ternary {
+            condition: Box::new(condition),
+            then_expr: Box::new(then_expr),
+            else_expr: Box::new(else_expr),
+        })
+    }
+
+    /// Precedence climbing over the left associative binary operators,
+    /// from `||` (level 0) down to `* / %` (level 9).
+    fn parse_binary(&mut self, level: usize) -> Result<ArithmeticExpr, ShellError> {
+        if level > 9 {
+            return self.parse_power();
+        }
+
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_op().and_then(|op| binary_operator(op, level)) {
+            self.advance();
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_power(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let base = self.parse_unary()?;
+        if self.eat_op("**") {
+            // `**` is right associative
+            let exponent = self.parse_power()?;
+            return Ok(binary(BinaryOperator::Pow, base, exponent));
+        }
+        Ok(base)
+    }
+
+    fn parse_unary(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let op = match self.peek_op() {
+            Some(op) => op,
+            None => return self.parse_postfix(),
+        };
+
+        match op {
+            "++" | "--" => {
+                self.advance();
+                let var = match self.current() {
+                    Some(ArithToken::Ident(name)) => name.clone(),
+                    _ => return Err(syntax_error(&format!("{}: operand must be a variable", op))),
+                };
+                self.advance();
+                Ok(if op == "++" {
+                    ArithmeticExpr::PreIncrement(var)
+                } else {
+                    ArithmeticExpr::PreDecrement(var)
+                })
+            }
+            "+" | "-" | "!" | "~" => {
+                self.advance();
+                let unary_op = match op {
+                    "+" => UnaryOperator::Plus,
+                    "-" => UnaryOperator::Negate,
+                    "!" => UnaryOperator::Not,
+                    _ => UnaryOperator::BitNot,
+                };
+                Ok(ArithmeticExpr::UnaryOp {
+                    op: unary_op,
+                    expr: Box::new(self.parse_unary()?),
+                })
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        let primary = self.parse_primary()?;
+        if let ArithmeticExpr::Variable(name) = &primary {
+            if self.eat_op("++") {
+                return Ok(ArithmeticExpr::PostIncrement(name.clone()));
+            }
+            if self.eat_op("--") {
+                return Ok(ArithmeticExpr::PostDecrement(name.clone()));
+            }
+        }
+        Ok(primary)
+    }
+
+    fn parse_primary(&mut self) -> Result<ArithmeticExpr, ShellError> {
+        match self.current().cloned() {
+            Some(ArithToken::Number(n)) => {
+                self.advance();
+                Ok(ArithmeticExpr::Literal(n))
+            }
+            Some(ArithToken::Ident(name)) => {
+                self.advance();
+                Ok(ArithmeticExpr::Variable(name))
+            }
+            Some(ArithToken::OpenParen) => {
+                self.advance();
+                let expr = self.parse_comma()?;
+                if self.current() != Some(&ArithToken::CloseParen) {
+                    return Err(syntax_error("expected `)'"));
+                }
+                self.advance();
+                Ok(expr)
+            }
+            Some(token) => Err(syntax_error(&format!("operand expected (error token is {})", describe(&token)))),
+            None => Err(syntax_error("operand expected")),
+        }
+    }
+}
+
+/// Parses an integer constant: decimal, `0x` hex, leading-zero octal or `base#digits`.
+pub fn parse_arith_number(text: &str) -> Result<i64, ShellError> {
+    let invalid = || ShellError::Eval(format!("{}: invalid arithmetic number", text));
+
+    if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=64).contains(&base) || digits.is_empty() {
+            return Err(ShellError::Eval(format!("{}: invalid arithmetic base", text)));
+        }
+        let mut value: i64 = 0;
+        for c in digits.chars() {
+            let digit = match c {
+                '0'..='9' => c as u32 - '0' as u32,
+                'a'..='z' => c as u32 - 'a' as u32 + 10,
+                // bases up to 36 are case insensitive
+                'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+                'A'..='Z' => c as u32 - 'A' as u32 + 36,
+                '@' => 62,
+                '_' => 63,
+                _ => return Err(invalid()),
+            };
+            if digit >= base {
+                return Err(ShellError::Eval(format!("{}: value too great for base", text)));
+            }
+            value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+        }
+        return Ok(value);
+    }
+
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (&text[1..], 8)
+    } else {
+        (text, 10)
+    };
+
+    u64::from_str_radix(digits, radix)
+        .map(|v| v as i64)
+        .map_err(|_| invalid())
+}
+
+fn tokenize_arithmetic(input: &str) -> Result<Vec<ArithToken>, ShellError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '#' | '@' | '_')) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(ArithToken::Number(parse_arith_number(&text)?));
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(ArithToken::Ident(chars[start..i].iter().collect()));
+            continue;
+        }
+
+        match c {
+            '(' => {
+                tokens.push(ArithToken::OpenParen);
+                i += 1;
+                continue;
+            }
+            ')' => {
+                tokens.push(ArithToken::CloseParen);
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+        match OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            Some(op) => {
+                tokens.push(ArithToken::Op(op));
+                i += op.chars().count();
+            }
+            None => {
+                return Err(syntax_error(&format!("invalid arithmetic operator (error token is \"{}\")", c)));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn binary_operator(op: &str, level: usize) -> Option<BinaryOperator> {
+    let found = match (level, op) {
+        (0, "||") => BinaryOperator::LogicalOr,
+        (1, "&&") => BinaryOperator::LogicalAnd,
+        (2, "|") => BinaryOperator::BitOr,
+        (3, "^") => BinaryOperator::BitXor,
+        (4, "&") => BinaryOperator::BitAnd,
+        (5, "==") => BinaryOperator::Eq,
+        (5, "!=") => BinaryOperator::Neq,
+        (6, "<") => BinaryOperator::Lt,
+        (6, ">") => BinaryOperator::Gt,
+        (6, "<=") => BinaryOperator::Le,
+        (6, ">=") => BinaryOperator::Ge,
+        (7, "<<") => BinaryOperator::ShiftLeft,
+        (7, ">>") => BinaryOperator::ShiftRight,
+        (8, "+") => BinaryOperator::Add,
+        (8, "-") => BinaryOperator::Sub,
+        (9, "*") => BinaryOperator::Mul,
+        (9, "/") => BinaryOperator::Div,
+        (9, "%") => BinaryOperator::Mod,
+        _ => return None,
+    };
+    Some(found)
+}
+
+fn binary(op: BinaryOperator, lhs: ArithmeticExpr, rhs: ArithmeticExpr) -> ArithmeticExpr {
+    ArithmeticExpr::BinaryOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+    }
+}
+
+fn describe(token: &ArithToken) -> String {
+    match token {
+        ArithToken::Number(n) => format!("\"{}\"", n),
+        ArithToken::Ident(name) => format!("\"{}\"", name),
+        ArithToken::Op(op) => format!("\"{}\"", op),
+        ArithToken::OpenParen => "\"(\"".to_string(),
+        ArithToken::CloseParen => "\")\"".to_string(),
+    }
+}
+
+fn syntax_error(msg: &str) -> ShellError {
+    ShellError::Syntax(format!("arithmetic: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<ArithmeticExpr> {
+        ArithmeticParser::new(text).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        match parse("1 + 2 * 3") {
+            Some(ArithmeticExpr::BinaryOp { op: BinaryOperator::Add, rhs, .. }) => {
+                assert!(matches!(*rhs, ArithmeticExpr::BinaryOp { op: BinaryOperator::Mul, .. }));
+            }
+            other => panic!("unexpected tree: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_expression_has_no_tree() {
+        assert!(parse("  ").is_none());
+    }
+
+    #[test]
+    fn number_bases() {
+        assert_eq!(parse_arith_number("0x1f").unwrap(), 31);
+        assert_eq!(parse_arith_number("017").unwrap(), 15);
+        assert_eq!(parse_arith_number("2#101").unwrap(), 5);
+        assert!(parse_arith_number("08").is_err());
+    }
+
+    #[test]
+    fn unbalanced_parenthesis_is_a_syntax_error() {
+        assert!(ArithmeticParser::new("(1 + 2").and_then(|mut p| p.parse()).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::envirement::is_name;
 use crate::lexer::types::{QuoteType, Token, Word, WordPart};
 use crate::parser::Parser;
 
@@ -9,13 +10,10 @@ impl Parser {
                     if let Some(eq_pos) = part.0.find('=') {
                         let mut result = Word{parts: vec![], quote : word.quote};
                         let key = part.0[..eq_pos].to_string();
-                        if eq_pos == part.0.len() - 1 && word.parts.len() == 1 {
-                            let next_token = self.tokens.get(pos + 1)?;
-                            if let Token::Word(val) = next_token {
-                                return Some((2, (key, Word{parts :val.parts.clone(), quote : word.quote})));
-                            } else {
-                                return None;
-                            }
+                        // `IFS= read` sets IFS to the empty string for `read`,
+                        // the next word is the command and not the value
+                        if part.1 != QuoteType::None || !is_name(&key) {
+                            return None;
                         }
                         let after_eq = &part.0[eq_pos + 1..];
                         if !after_eq.is_empty() {
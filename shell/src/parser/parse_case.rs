@@ -0,0 +1,136 @@
+use crate::error::ShellError;
+use crate::lexer::types::{QuoteType, Token, WordPart};
+use crate::parser::Parser;
+use crate::parser::types::*;
+
+impl Parser {
+    pub fn parse_case(&mut self) -> Result<Option<AstNode>, ShellError> {
+        // Check for `case`
+        let word = match self.current() {
+            Some(Token::Word(word)) => word,
+            _ => return Ok(None),
+        };
+
+        if word.parts.len() != 1 || word.quote != QuoteType::None {
+            return Ok(None);
+        }
+
+        if let WordPart::Literal(s) = &word.parts[0] {
+            if s.0 != "case" || s.1 != QuoteType::None {
+                return Ok(None);
+            }
+        } else {
+            return Ok(None);
+        }
+
+        self.advance(); // consume `case`
+
+        // Parse the word being matched
+        let subject = match self.current() {
+            Some(Token::Word(word)) => {
+                let tmp = word.clone();
+                self.advance();
+                tmp
+            }
+            _ => {
+                return Err(ShellError::Parse("Expected word after 'case'".into()));
+            }
+        };
+
+        self.skip_newlines();
+        self.expect_word("in")?;
+        self.skip_newlines();
+
+        let mut arms = Vec::new();
+
+        loop {
+            // comments before the next pattern or `esac` end the arm above
+            if let Some((_, body)) = arms.last_mut() {
+                let mut comments = Vec::new();
+                self.take_comments(&mut comments);
+                if !comments.is_empty() {
+                    *body = match std::mem::replace(body, AstNode::Sequence(vec![])) {
+                        AstNode::Sequence(mut nodes) => {
+                            nodes.extend(comments);
+                            AstNode::Sequence(nodes)
+                        }
+                        node => AstNode::Sequence([vec![node], comments].concat()),
+                    };
+                }
+            }
+
+            if self.is_eof() {
+                return Err(ShellError::Syntax("Expected 'esac' to close 'case'".into()));
+            }
+
+            if self.expect_word("esac").is_ok() {
+                break;
+            }
+
+            // Optional `(` before the pattern list
+            if let Some(Token::OpenParen) = self.current() {
+                self.advance();
+            }
+
+            // Parse `pat1 | pat2 ... )`
+            let mut patterns = Vec::new();
+            loop {
+                match self.current() {
+                    Some(Token::Word(word)) => {
+                        patterns.push(word.clone());
+                        self.advance();
+                    }
+                    _ => {
+                        return Err(ShellError::Parse("Expected pattern in case arm".into()));
+                    }
+                }
+
+                match self.current() {
+                    Some(Token::Pipe) => self.advance(),
+                    Some(Token::CloseParen) => {
+                        self.advance();
+                        break;
+                    }
+                    _ => {
+                        return Err(ShellError::Parse(
+                            "Expected ')' after case pattern".into(),
+                        ));
+                    }
+                }
+            }
+
+            self.skip_newlines();
+
+            // Parse the arm body, which may be empty
+            let body = match self.current() {
+                Some(Token::DoubleSemicolon) => AstNode::Sequence(vec![]),
+                _ if self.is_reserved_word() => AstNode::Sequence(vec![]),
+                _ => match self.parse_sequence(true)? {
+                    Some(cmd) => cmd,
+                    None => AstNode::Sequence(vec![]),
+                },
+            };
+
+            arms.push((patterns, body));
+
+            // `;;` is optional before `esac`
+            match self.current() {
+                Some(Token::DoubleSemicolon) => {
+                    self.advance();
+                    self.skip_newlines();
+                }
+                _ => {
+                    self.skip_newlines();
+                    self.expect_word("esac")?;
+                    break;
+                }
+            }
+        }
+
+        Ok(Some(AstNode::Case {
+            word: subject,
+            arms,
+            redirects: self.parse_compound_redirects()?,
+        }))
+    }
+}
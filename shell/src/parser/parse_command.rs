@@ -13,6 +13,10 @@ impl Parser {
                 return Ok(Some(group));
         }
 
+        if let Some(subshell) = self.parse_subshell()? {
+            return Ok(Some(subshell));
+        }
+
         let mut assignments = Vec::new();
         let mut current_pos = self.pos;
 
@@ -72,6 +76,16 @@ impl Parser {
                     self.advance();
                     current_pos = self.pos;
                 }
+                // braces are only reserved where a command starts
+                Token::OpenBrace | Token::CloseBrace if !cmd_word.parts.is_empty() => {
+                    let brace = if *token == Token::OpenBrace { "{" } else { "}" };
+                    args.push(Word {
+                        parts: vec![WordPart::Literal((brace.to_string(), QuoteType::None))],
+                        quote: QuoteType::None,
+                    });
+                    self.advance();
+                    current_pos = self.pos;
+                }
                 Token::LogicalNot => {
                     if cmd_word.parts.len() != 0 {
                         args.push(Word {
@@ -17,12 +17,22 @@ impl Parser {
             _ => false,
         };
 
-        let node = if let Some(if_node) = self.parse_if()? {
+        let node = if let Some(Token::Arithmetic(expr)) = self.current() {
+            let node = AstNode::ArithmeticCommand(expr.clone());
+            self.advance();
+            if should_negate {
+                AstNode::Not(Box::new(node))
+            } else {
+                node
+            }
+        } else if let Some(if_node) = self.parse_if()? {
             if_node
         }else if let Some(while_node) = self.parse_while_or_until()? {
             while_node
         }else if let Some(for_node) = self.parse_for()? {
             for_node
+        }else if let Some(case_node) = self.parse_case()? {
+            case_node
         }else {
             match self.parse_command()? {
                 Some(cmd) => {
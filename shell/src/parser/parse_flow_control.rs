@@ -17,6 +17,7 @@ impl Parser {
         let cmd = match &word.parts[0] {
             WordPart::Literal(s) if s.0 == "break" => "break",
             WordPart::Literal(s) if s.0 == "continue" => "continue",
+            WordPart::Literal(s) if s.0 == "return" => "return",
             _ => return Ok(None),
         };
 
@@ -36,6 +37,7 @@ impl Parser {
         let node = match cmd {
             "break" => AstNode::Break(level_word),
             "continue" => AstNode::Continue(level_word),
+            "return" => AstNode::Return(level_word),
             _ => unreachable!(),
         };
 
@@ -85,6 +85,7 @@ impl Parser {
             var,
             values,
             body: Box::new(body),
+            redirects: self.parse_compound_redirects()?,
         }))
     }
 }
\ No newline at end of file
@@ -1,72 +1,68 @@
 use crate::error::ShellError;
-use crate::lexer::types::{QuoteType, Token, Word, WordPart};
+use crate::lexer::types::Token;
 use crate::parser::Parser;
 use crate::parser::types::*;
 
 impl Parser {
+    /// `name() body` or `function name [()] body`, where the body is any
+    /// compound command. Its redirections are set up on every call.
     pub fn parse_function(&mut self) -> Result<Option<AstNode>, ShellError> {
         let start_pos = self.pos;
+        let keyword = self.is_word("function");
+        if keyword {
+            self.advance();
+        }
+
         let name = match self.current() {
             Some(Token::Word(word)) => word.clone(),
+            _ if keyword => {
+                return Err(ShellError::Parse("Expected function name after `function'".into()));
+            }
             _ => return Ok(None),
         };
-
-        self.advance();
-
-        if !matches!(self.current(), Some(Token::OpenParen)) {
-            self.pos = start_pos;
-            return Ok(None);
-        }
         self.advance();
 
-        if !matches!(self.current(), Some(Token::CloseParen)) {
+        // `function name ( list )` has a subshell body, not an empty `()`
+        let parens = matches!(self.current(), Some(Token::OpenParen))
+            && matches!(self.look_ahead(1), Some(Token::CloseParen));
+        if parens {
+            self.advance();
+            self.advance();
+        } else if !keyword {
             self.pos = start_pos;
             return Ok(None);
         }
-        self.advance();
+        self.skip_newlines();
 
-        let body = match self.current() {
-            Some(Token::OpenBrace) => match self.parse_group()? {
-                Some(body) => body,
-                None => return Err(ShellError::Parse("Empty function body1".into())),
-            },
-            Some(Token::Word(word)) => {
-                if let Some(WordPart::Literal(content)) = word.parts.get(0) {
-                    if content.0.starts_with('{') {
-                        let remaining = &content.0[1..];
-                        if !remaining.is_empty() {
-                            let remaining_word = Word {
-                                parts: vec![WordPart::Literal((remaining.to_string(), QuoteType::None))],
-                                quote : word.quote
-                            };
-                            self.tokens[self.pos] = Token::OpenBrace;
-                            self.tokens
-                                .insert(self.pos + 1, Token::Word(remaining_word));
-                        }
-                        match self.parse_group()? {
-                            Some(body) => body,
-                            None => {
-                                return Err(ShellError::Parse("Empty function body".into()));
-                            }
-                        }
-                    } else {
-                        self.pos = start_pos;
-                        return Ok(None);
-                    }
-                } else {
-                    self.pos = start_pos;
-                    return Ok(None);
-                }
-            }
-            _ => {
-                self.pos = start_pos;
-                return Ok(None);
-            }
+        let body = match self.parse_compound_command()? {
+            Some(body) => body,
+            None => return Err(ShellError::Parse("Expected function body".into())),
         };
 
         Ok(Some(AstNode::FunctionDef {
-            name: name.clone(),
+            name,
             body: Box::new(body),
         }))
     }
+
+    /// A `{ }` group, `( )` subshell, `if`, `while`, `until`, `for` or
+    /// `case` command.
+    pub fn parse_compound_command(&mut self) -> Result<Option<AstNode>, ShellError> {
+        if let Some(node) = self.parse_group()? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.parse_subshell()? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.parse_if()? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.parse_while_or_until()? {
+            return Ok(Some(node));
+        }
+        if let Some(node) = self.parse_for()? {
+            return Ok(Some(node));
+        }
+        self.parse_case()
+    }
 }
@@ -23,13 +23,15 @@ impl Parser {
         let mut commands = Vec::new();
 
         loop {
+            self.skip_newlines();
+            self.take_comments(&mut commands);
             match self.current() {
                 Some(Token::CloseBrace) => break,
                 None => return Err(ShellError::Parse("Unexpected EOF in command group".into())),
                 _ => {}
             }
 
-            if let Some(cmd) = self.parse_pipeline()? {
+            if let Some(cmd) = self.parse_op()? {
                 commands.push(cmd);
             } else {
                 return Err(ShellError::Parse("Expected command in group".into()));
@@ -54,23 +56,7 @@ impl Parser {
             return Err(ShellError::Parse("Empty command group".into()));
         }
 
-        let mut redirects: Vec<Redirect> = vec![];
-        let mut current_pos = self.pos;
-
-        while self.current().is_some() {
-            match self.parse_redirection(current_pos) {
-                Ok(Some((advance_by, redirect))) => {
-                    redirects.push(redirect);
-                    current_pos += advance_by;
-                    self.pos = current_pos;
-                    continue;
-                }
-                Ok(None) => {
-                    break;
-                }
-                Err(e) => return Err(e),
-            }
-        }
+        let redirects = self.parse_compound_redirects()?;
 
         return Ok(Some(AstNode::Group {
             commands,
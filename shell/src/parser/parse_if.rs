@@ -91,6 +91,7 @@ impl Parser {
             then_branch: Box::new(then_branch),
             elif,
             else_branch,
+            redirects: self.parse_compound_redirects()?,
         }))
     }
 }
@@ -5,7 +5,7 @@ use crate::parser::types::*;
 
 impl Parser {
     pub fn parse_op(&mut self) -> Result<Option<AstNode>, ShellError> {
-        let mut left = match self.parse_command_or_if()? {
+        let mut left = match self.parse_pipeline()? {
             Some(node) => node,
             None => {
                 return Ok(None);
@@ -16,8 +16,9 @@ impl Parser {
             match token {
                 Token::LogicalAnd => {
                     self.advance();
+                    self.skip_newlines();
 
-                    let right = match self.parse_command_or_if()? {
+                    let right = match self.parse_pipeline()? {
                         Some(node) => node,
                         None => {
                             return Err(ShellError::Parse("expected command after &&".into()));
@@ -29,6 +30,7 @@ impl Parser {
 
                 Token::LogicalOr => {
                     self.advance();
+                    self.skip_newlines();
                     let right = match self.parse_op()? {
                         Some(command) => command,
                         None => {
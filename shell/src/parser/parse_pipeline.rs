@@ -7,7 +7,7 @@ impl Parser {
     pub fn parse_pipeline(&mut self) -> Result<Option<AstNode>, ShellError> {
         let mut commands = Vec::new();
 
-        let first_command = match self.parse_op()? {
+        let first_command = match self.parse_command_or_if()? {
             Some(command) => command,
             None => return Ok(None),
         };
@@ -15,7 +15,9 @@ impl Parser {
 
         while let Some(Token::Pipe) = self.current() {
             self.advance();
-            let next_command = match self.parse_op()? {
+            // a newline may follow the operator
+            self.skip_newlines();
+            let next_command = match self.parse_command_or_if()? {
                 Some(command) => command,
                 None => {
                     return Err(ShellError::Parse(String::from(
@@ -4,6 +4,16 @@ use crate::parser::Parser;
 use crate::parser::types::*;
 
 impl Parser {
+    /// The redirections after the closing word of a compound command.
+    pub fn parse_compound_redirects(&mut self) -> Result<Vec<Redirect>, ShellError> {
+        let mut redirects = Vec::new();
+        while let Some((advance_by, redirect)) = self.parse_redirection(self.pos)? {
+            redirects.push(redirect);
+            self.pos += advance_by;
+        }
+        Ok(redirects)
+    }
+
     pub fn parse_redirection(&self, pos: usize) -> Result<Option<(usize, Redirect)>, ShellError> {
         let current_token = self.tokens.get(pos).ok_or_else(|| {
             ShellError::Parse("Unexpected end of input while parsing redirection".into())
@@ -38,7 +48,7 @@ impl Parser {
                     let redirect = Redirect {
                         fd: None,
                         target: target.clone(),
-                        kind: RedirectOp::Write,
+                        kind: RedirectOp::Read,
                     };
                     Ok(Some((2, redirect)))
                 } else {
@@ -66,21 +76,61 @@ impl Parser {
                     ))
                 }
             }
-            Token::RedirectHereDoc => {
+            Token::RedirectHereDoc { fd, strip_tabs } => {
                 let target_token = self
                     .tokens
                     .get(pos + 1)
                     .ok_or_else(|| ShellError::Parse("Expected delimiter after '<<'".into()))?;
+                // the tokenizer has already swapped the delimiter for the body
+                if let Token::HereDoc { delimiter, body } = target_token {
+                    let redirect = Redirect {
+                        fd: *fd,
+                        target: body.clone(),
+                        kind: RedirectOp::HereDoc {
+                            delimiter: delimiter.clone(),
+                            strip_tabs: *strip_tabs,
+                        },
+                    };
+                    Ok(Some((2, redirect)))
+                } else {
+                    Err(ShellError::Parse(
+                        "Expected word token as heredoc delimiter after '<<'".into(),
+                    ))
+                }
+            }
+            Token::RedirectHereString(fd) => {
+                let target_token = self
+                    .tokens
+                    .get(pos + 1)
+                    .ok_or_else(|| ShellError::Parse("Expected word after '<<<'".into()))?;
                 if let Token::Word(target) = target_token {
                     let redirect = Redirect {
-                        fd: None,
+                        fd: *fd,
                         target: target.clone(),
-                        kind: RedirectOp::HereDoc,
+                        kind: RedirectOp::HereString,
                     };
                     Ok(Some((2, redirect)))
                 } else {
                     Err(ShellError::Parse(
-                        "Expected word token as heredoc delimiter after '<<'".into(),
+                        "Expected word after redirection operator '<<<'".into(),
+                    ))
+                }
+            }
+            Token::RedirectReadWrite(fd) => {
+                let target_token = self
+                    .tokens
+                    .get(pos + 1)
+                    .ok_or_else(|| ShellError::Parse("Expected target after '<>'".into()))?;
+                if let Token::Word(target) = target_token {
+                    let redirect = Redirect {
+                        fd: *fd,
+                        target: target.clone(),
+                        kind: RedirectOp::ReadWrite,
+                    };
+                    Ok(Some((2, redirect)))
+                } else {
+                    Err(ShellError::Parse(
+                        "Expected filename after redirection operator '<>'".into(),
                     ))
                 }
             }
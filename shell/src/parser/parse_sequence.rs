@@ -8,7 +8,13 @@ impl Parser {
         let mut commands = Vec::new();
 
         loop {
-            if let Some(cmd) = self.parse_pipeline()? {
+            self.skip_newlines();
+            self.take_comments(&mut commands);
+            if in_if_condition && self.is_reserved_word() {
+                break;
+            }
+
+            if let Some(cmd) = self.parse_op()? {
                 commands.push(cmd);
             } else {
                 break;
@@ -30,6 +36,8 @@ impl Parser {
                         break;
                     }
                 }
+                // `;;` closes a case arm, the arm body ends here
+                Some(Token::DoubleSemicolon) => break,
                 _ => {
                     if in_if_condition {
                         return Err(ShellError::Parse(
@@ -42,6 +50,7 @@ impl Parser {
             }
         }
 
+        self.take_comments(&mut commands);
         if commands.is_empty() {
             Ok(None)
         } else if commands.len() == 1 {
@@ -0,0 +1,30 @@
+use crate::error::ShellError;
+use crate::lexer::types::Token;
+use crate::parser::Parser;
+use crate::parser::types::*;
+
+impl Parser {
+    /// `( list )`, run in a forked copy of the shell.
+    pub fn parse_subshell(&mut self) -> Result<Option<AstNode>, ShellError> {
+        if !matches!(self.current(), Some(Token::OpenParen)) {
+            return Ok(None);
+        }
+        self.advance();
+
+        let body = match self.parse_sequence(false)? {
+            Some(body) => body,
+            None => return Err(ShellError::Parse("Expected command in subshell".into())),
+        };
+        self.skip_newlines();
+
+        if !matches!(self.current(), Some(Token::CloseParen)) {
+            return Err(ShellError::Parse("Expected `)' to close subshell".into()));
+        }
+        self.advance();
+
+        Ok(Some(AstNode::Subshell {
+            body: Box::new(body),
+            redirects: self.parse_compound_redirects()?,
+        }))
+    }
+}
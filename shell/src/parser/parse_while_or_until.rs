@@ -52,15 +52,18 @@ impl Parser {
         };
 
         self.expect_word("done")?;
+        let redirects = self.parse_compound_redirects()?;
 
         Ok(Some(match loop_type {
             "while" => AstNode::While {
                 condition: Box::new(condition),
                 body: Box::new(body),
+                redirects,
             },
             "until" => AstNode::Until {
                 condition: Box::new(condition),
                 body: Box::new(body),
+                redirects,
             },
             _ => unreachable!(),
         }))
@@ -1,4 +1,4 @@
-use crate::{envirement::ShellEnv, lexer::types::Word};
+use crate::lexer::types::Word;
 use std::fmt;
 
 // Arithmetic expression AST
@@ -19,10 +19,25 @@ pub enum ArithmeticExpr {
         var: String,
         value: Box<ArithmeticExpr>,
     },
+    CompoundAssignment {
+        var: String,
+        op: BinaryOperator,
+        value: Box<ArithmeticExpr>,
+    },
+    Ternary {
+        condition: Box<ArithmeticExpr>,
+        then_expr: Box<ArithmeticExpr>,
+        else_expr: Box<ArithmeticExpr>,
+    },
+    PreIncrement(String),  // ++x
+    PreDecrement(String),  // --x
+    PostIncrement(String), // x++
+    PostDecrement(String), // x--
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum UnaryOperator {
+    Plus,   // +x
     Negate, // -x
     Not,    // !x
     BitNot, // ~x
@@ -35,6 +50,7 @@ pub enum BinaryOperator {
     Mul, // *
     Div, // /
     Mod, // %
+    Pow, // **
     Eq,  // ==
     Neq, // !=
     Lt,  // <
@@ -50,6 +66,8 @@ pub enum BinaryOperator {
 
     LogicalAnd, // &&
     LogicalOr,  // ||
+
+    Comma, // ,
 }
 
 // I/O Redirection support
@@ -61,10 +79,12 @@ pub enum RedirectOp {
     Append,
     /// `<`: redirect stdin from a file
     Read,
-    /// `<<`: here-document
-    HereDoc,
+    /// `<<`: here-document, the redirect target holds the body
+    HereDoc { delimiter: Word, strip_tabs: bool },
     /// `<>`: open file for read and write
     ReadWrite,
+    /// `<<<`: here-string, the expanded word followed by a newline
+    HereString,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -90,7 +110,10 @@ pub enum AstNode {
     Not(Box<AstNode>),
     Background(Box<AstNode>),
 
-    Subshell(Box<AstNode>),
+    Subshell {
+        body: Box<AstNode>,
+        redirects: Vec<Redirect>,
+    },
     Group {
         commands: Vec<AstNode>,
         redirects: Vec<Redirect>,
@@ -101,31 +124,43 @@ pub enum AstNode {
         then_branch: Box<AstNode>,
         elif: Vec<(Box<AstNode>, Box<AstNode>)>,
         else_branch: Option<Box<AstNode>>,
+        redirects: Vec<Redirect>,
     },
     While {
         condition: Box<AstNode>,
         body: Box<AstNode>,
+        redirects: Vec<Redirect>,
     },
     Until {
         condition: Box<AstNode>,
         body: Box<AstNode>,
+        redirects: Vec<Redirect>,
     },
     For {
         var: String,
         values: Vec<Word>,
         body: Box<AstNode>,
+        redirects: Vec<Redirect>,
+    },
+    Case {
+        word: Word,
+        arms: Vec<(Vec<Word>, AstNode)>,
+        redirects: Vec<Redirect>,
     },
-    // Case {
-    //     word: String,
-    //     arms: Vec<(Vec<String>, AstNode)>,
-    // },
     FunctionDef {
         name: Word,
         body: Box<AstNode>,
     },
     Break(Option<Word>),
     Continue(Option<Word>),
-    // ArithmeticCommand(ArithmeticExpr),
+    // `return [n]`, leaves the running function
+    Return(Option<Word>),
+    // The text is kept unparsed, like `WordPart::ArithmeticSubstitution`,
+    // because it may contain `$var` that only expands at run time
+    ArithmeticCommand(String),
+    // `# text`, only in a tree parsed for `--fmt`. A trailing comment
+    // followed a command on the same line
+    Comment { text: String, trailing: bool },
 }
 
 impl fmt::Display for AstNode {
@@ -135,6 +170,21 @@ impl fmt::Display for AstNode {
 }
 
 impl AstNode {
+    /// The redirections of a compound command, set up for as long as it
+    /// runs. A simple command opens its own when it starts.
+    pub fn compound_redirects(&self) -> &[Redirect] {
+        match self {
+            AstNode::Subshell { redirects, .. }
+            | AstNode::Group { redirects, .. }
+            | AstNode::If { redirects, .. }
+            | AstNode::While { redirects, .. }
+            | AstNode::Until { redirects, .. }
+            | AstNode::For { redirects, .. }
+            | AstNode::Case { redirects, .. } => redirects,
+            _ => &[],
+        }
+    }
+
     pub fn fmt_with_indent(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
         let spaces = "  ".repeat(indent);
 
@@ -211,112 +261,3 @@ impl fmt::Display for Redirect {
         )
     }
 }
-
-impl Redirect {
-    pub fn to_text(&self, env: &ShellEnv) -> String {
-        let fd_str = self.fd.map(|fd| fd.to_string()).unwrap_or_default();
-        let op_str = match self.kind {
-            RedirectOp::Write => ">",
-            RedirectOp::Append => ">>",
-            RedirectOp::Read => "<",
-            RedirectOp::HereDoc => "<<",
-            RedirectOp::ReadWrite => "<>",
-        };
-        format!("{}{}{}", fd_str, op_str, self.target.expand(env))
-    }
-}
-
-impl AstNode {
-    pub fn to_text(&self, env: &ShellEnv) -> String {
-        match self {
-            AstNode::Command { cmd, args, assignments, redirects } => {
-                let mut parts = Vec::new();
-                for (k, v) in assignments {
-                    parts.push(format!("{}={}", k, v.expand(env)));
-                }
-                parts.push(cmd.expand(env));
-                for arg in args {
-                    parts.push(arg.expand(env));
-                }
-                for r in redirects {
-                    parts.push(r.to_text(env));
-                }
-                parts.join(" ")
-            }
-
-            AstNode::Pipeline(nodes) => 
-                nodes.iter().map(|n| n.to_text(env)).collect::<Vec<_>>().join(" | "),
-
-            AstNode::Sequence(nodes) => 
-                nodes.iter().map(|n| n.to_text(env)).collect::<Vec<_>>().join("; "),
-
-            AstNode::And(lhs, rhs) => 
-                format!("{} && {}", lhs.to_text(env), rhs.to_text(env)),
-
-            AstNode::Or(lhs, rhs) => 
-                format!("{} || {}", lhs.to_text(env), rhs.to_text(env)),
-
-            AstNode::Not(node) => 
-                format!("! {}", node.to_text(env)),
-
-            AstNode::Background(node) => 
-                format!("{} &", node.to_text(env)),
-
-            AstNode::Subshell(node) => 
-                format!("(${})", node.to_text(env)),
-
-            AstNode::Group { commands, redirects } => {
-                let mut s = format!(
-                    "{{ {}; }}",
-                    commands.iter().map(|c| c.to_text(env)).collect::<Vec<_>>().join("; ")
-                );
-                for r in redirects {
-                    s.push_str(&format!(" {}", r.to_text(env)));
-                }
-                s
-            }
-
-            AstNode::If { condition, then_branch, elif, else_branch } => {
-                let mut s = format!("if {}; then {}", condition.to_text(env), then_branch.to_text(env));
-                for (cond, body) in elif {
-                    s.push_str(&format!(" elif {}; then {}", cond.to_text(env), body.to_text(env)));
-                }
-                if let Some(else_b) = else_branch {
-                    s.push_str(&format!(" else {}", else_b.to_text(env)));
-                }
-                s.push_str(" fi");
-                s
-            }
-
-            AstNode::While { condition, body } => 
-                format!("while {}; do {}; done", condition.to_text(env), body.to_text(env)),
-
-            AstNode::Until { condition, body } => 
-                format!("until {}; do {}; done", condition.to_text(env), body.to_text(env)),
-
-            AstNode::For { var, values, body } => {
-                // let vals = values.iter().reduce(|w1, w2| {
-                //     return w1.expand(env);
-                // });
-                // format!("for {} in {}; do {}; done", "".to_string(),"".to_string(), "".to_string() body.to_text(env))
-                return String::new();
-            }
-
-            // AstNode::Case { word, arms } => {
-            //     let mut s = format!("case {} in", word);
-            //     for (pats, body) in arms {
-            //         let pat_str = pats.join(" | ");
-            //         s.push_str(&format!(" {} ) {} ;;", pat_str, body.to_text(env)));
-            //     }
-            //     s.push_str(" esac");
-            //     s
-            // }
-
-            AstNode::FunctionDef { name, body } => 
-                format!("{}() {{ {}; }}", name.expand(env), body.to_text(env)),
-
-          _ =>{
-                format!("{:?}", self)
-          }}
-    }
-}
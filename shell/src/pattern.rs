@@ -0,0 +1,198 @@
+/// Shell pattern matching (`*`, `?`, `[...]`) as used by `case` arms.
+///
+/// A backslash makes the next character literal, which is how quoted parts of
+/// a word are kept from acting as wildcards.
+pub fn pattern_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let mut pi = 0;
+    let mut ti = 0;
+    // position of the last `*` seen and the text index it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < text.len() {
+        if pi < pattern.len() {
+            match pattern[pi] {
+                '*' => {
+                    star = Some((pi, ti));
+                    pi += 1;
+                    continue;
+                }
+                '?' => {
+                    pi += 1;
+                    ti += 1;
+                    continue;
+                }
+                '[' => match match_bracket(&pattern, pi, text[ti]) {
+                    Some((true, next)) => {
+                        pi = next;
+                        ti += 1;
+                        continue;
+                    }
+                    Some((false, _)) => {}
+                    None => {
+                        // no closing `]`, the bracket is an ordinary character
+                        if text[ti] == '[' {
+                            pi += 1;
+                            ti += 1;
+                            continue;
+                        }
+                    }
+                },
+                '\\' if pi + 1 < pattern.len() => {
+                    if pattern[pi + 1] == text[ti] {
+                        pi += 2;
+                        ti += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[ti] {
+                        pi += 1;
+                        ti += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        match star {
+            Some((star_pi, star_ti)) => {
+                pi = star_pi + 1;
+                ti = star_ti + 1;
+                star = Some((star_pi, star_ti + 1));
+            }
+            None => return false,
+        }
+    }
+
+    while pi < pattern.len() && pattern[pi] == '*' {
+        pi += 1;
+    }
+    pi == pattern.len()
+}
+
+/// Escapes every character that has a meaning in a pattern.
+pub fn escape_pattern(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Matches `c` against the bracket expression starting at `pattern[start]`.
+/// Returns whether it matched and the index right after the closing `]`,
+/// or `None` if the bracket is never closed.
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let current = *pattern.get(i)?;
+        if current == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+
+        if current == '['
+            && pattern.get(i + 1) == Some(&':')
+            && let Some(end) = find_class_end(pattern, i + 2)
+        {
+            let name: String = pattern[i + 2..end].iter().collect();
+            if class_matches(&name, c) {
+                matched = true;
+            }
+            i = end + 2;
+            continue;
+        }
+
+        let low = if current == '\\' {
+            i += 1;
+            *pattern.get(i)?
+        } else {
+            current
+        };
+        i += 1;
+
+        if pattern.get(i) == Some(&'-') && pattern.get(i + 1).is_some_and(|&n| n != ']') {
+            let mut high = pattern[i + 1];
+            i += 2;
+            if high == '\\' {
+                high = *pattern.get(i)?;
+                i += 1;
+            }
+            if low <= c && c <= high {
+                matched = true;
+            }
+        } else if low == c {
+            matched = true;
+        }
+    }
+}
+
+fn find_class_end(pattern: &[char], from: usize) -> Option<usize> {
+    (from..pattern.len().saturating_sub(1)).find(|&i| pattern[i] == ':' && pattern[i + 1] == ']')
+}
+
+fn class_matches(name: &str, c: char) -> bool {
+    match name {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        "cntrl" => c.is_control(),
+        "print" => !c.is_control(),
+        "graph" => !c.is_control() && !c.is_whitespace(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(pattern_matches("*.rs", "main.rs"));
+        assert!(pattern_matches("a*b*c", "abxbc"));
+        assert!(pattern_matches("?x", "ax"));
+        assert!(!pattern_matches("?x", "x"));
+        assert!(pattern_matches("*", ""));
+        assert!(!pattern_matches("a*", "ba"));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(pattern_matches("[a-c]x", "bx"));
+        assert!(!pattern_matches("[!a-c]x", "bx"));
+        assert!(pattern_matches("[^a-c]x", "dx"));
+        assert!(pattern_matches("[]a]", "]"));
+        // without a closing `]` the bracket is literal
+        assert!(pattern_matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escaped_characters_are_literal() {
+        assert!(pattern_matches("\\*", "*"));
+        assert!(!pattern_matches("\\*", "a"));
+        let text = "a*b?[c]\\";
+        assert!(pattern_matches(&escape_pattern(text), text));
+        assert!(!pattern_matches(&escape_pattern("a*"), "abc"));
+    }
+}
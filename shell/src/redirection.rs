@@ -1,26 +1,49 @@
 use crate::envirement::ShellEnv;
 use crate::error::ShellError;
+use crate::expansion::expand_tilde;
 use crate::parser::types::*;
 use std::collections::HashMap;
 use std::fs::OpenOptions;
-use std::os::fd::{FromRawFd, OwnedFd};
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use nix::fcntl::{FcntlArg, fcntl};
 use nix::unistd::close;
 use nix::unistd::dup;
+use nix::unistd::dup2;
 
 pub fn setup_redirections_ownedfds(
-    redirects: &Vec<Redirect>,
-    env: &ShellEnv,
+    redirects: &[Redirect],
+    env: &mut ShellEnv,
 ) -> Result<HashMap<u64, OwnedFd>, ShellError> {
     let mut fds_map = HashMap::new();
 
     for redirect in redirects {
         let fd = redirect.fd.unwrap_or_else(|| match redirect.kind {
-            RedirectOp::Read => 0, // stdin
-            _ => 1,                // stdout by default
+            RedirectOp::Read
+            | RedirectOp::HereDoc { .. }
+            | RedirectOp::HereString
+            | RedirectOp::ReadWrite => 0, // stdin
+            _ => 1,                       // stdout by default
         });
 
-        let target = redirect.target.expand(env);
+        match redirect.kind {
+            RedirectOp::HereDoc { .. } => {
+                let body = redirect.target.try_expand(env)?;
+                fds_map.insert(fd, heredoc_fd(&body)?);
+                continue;
+            }
+            RedirectOp::HereString => {
+                let body = redirect.target.try_expand(env)? + "\n";
+                fds_map.insert(fd, heredoc_fd(&body)?);
+                continue;
+            }
+            _ => {}
+        }
+
+        let target = expand_tilde(&redirect.target, env, false).try_expand(env)?;
 
         // Handle cases like <&- (close FD)
         if target.trim() == "&-" {
@@ -36,6 +59,12 @@ pub fn setup_redirections_ownedfds(
         // Handle cases like 2>&1 (duplicate fds)
         if let Some(stripped) = target.strip_prefix('&') {
             match stripped.parse::<i32>() {
+                // an fd redirected earlier in the same list, as in `>file 2>&1`
+                Ok(target_fd) if fds_map.contains_key(&(target_fd as u64)) => {
+                    let owned: &OwnedFd = &fds_map[&(target_fd as u64)];
+                    let cloned = owned.try_clone()?;
+                    fds_map.insert(fd, cloned);
+                }
                 Ok(target_fd) => match dup(target_fd) {
                     Ok(dup_fd) => {
                         let owned = unsafe { OwnedFd::from_raw_fd(dup_fd) };
@@ -71,6 +100,12 @@ pub fn setup_redirections_ownedfds(
                 .create(true)
                 .append(true)
                 .open(&target),
+            RedirectOp::ReadWrite => OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&target),
             _ => {
                 return Err(ShellError::Exec(format!(
                     "Unsupported redirection: {:?}",
@@ -85,7 +120,7 @@ pub fn setup_redirections_ownedfds(
                 fds_map.insert(fd, owned_fd);
             }
             Err(e) => {
-                return Err(ShellError::Exec(format!(
+                return Err(ShellError::Push(format!(
                     "Redirection failed for fd {}: {}",
                     fd, e
                 )));
@@ -95,3 +130,74 @@ pub fn setup_redirections_ownedfds(
 
     Ok(fds_map)
 }
+
+/// Opens `redirects` and points the shell's own fds at them, see
+/// `redirect_shell_fds`.
+pub fn apply_redirects(
+    redirects: &[Redirect],
+    env: &mut ShellEnv,
+) -> Result<Vec<(u64, Option<RawFd>)>, ShellError> {
+    if redirects.is_empty() {
+        return Ok(Vec::new());
+    }
+    redirect_shell_fds(&setup_redirections_ownedfds(redirects, env)?)
+}
+
+/// Points the shell's own fds at `fds_map`, for commands that run inside the
+/// shell. Gives back a copy of each fd it replaced, `None` for one that was
+/// closed, to hand to `restore_shell_fds`.
+pub fn redirect_shell_fds(
+    fds_map: &HashMap<u64, OwnedFd>,
+) -> Result<Vec<(u64, Option<RawFd>)>, ShellError> {
+    // output buffered so far belongs to the old fds
+    let _ = std::io::stdout().flush();
+    let mut saved = Vec::new();
+    for (&fd, owned_fd) in fds_map {
+        // above the fds scripts use and closed on exec, so commands run
+        // while the redirection is in place never see the copy
+        saved.push((fd, fcntl(fd as RawFd, FcntlArg::F_DUPFD_CLOEXEC(10)).ok()));
+        if let Err(e) = dup2(owned_fd.as_raw_fd(), fd as RawFd) {
+            restore_shell_fds(saved);
+            return Err(ShellError::Exec(format!("dup2 failed for fd {}: {}", fd, e)));
+        }
+    }
+    Ok(saved)
+}
+
+/// Undoes `redirect_shell_fds`, in reverse order.
+pub fn restore_shell_fds(saved: Vec<(u64, Option<RawFd>)>) {
+    let _ = std::io::stdout().flush();
+    for (fd, backup) in saved.into_iter().rev() {
+        match backup {
+            Some(backup) => {
+                let _ = dup2(backup, fd as RawFd);
+                let _ = close(backup);
+            }
+            None => {
+                let _ = close(fd as RawFd);
+            }
+        }
+    }
+}
+
+/// Writes a here-document or here-string body to an unlinked temporary file
+/// and returns it rewound, a pipe could block on bodies larger than its buffer.
+fn heredoc_fd(body: &str) -> Result<OwnedFd, ShellError> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "push-heredoc-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    file.write_all(body.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(OwnedFd::from(file))
+}
@@ -77,6 +77,8 @@ impl Shell {
         };
 
         self.buffer.clear();
+        self.pending_input.clear();
+        self.cursor_position.reset();
         Self::print_out_static(stdout , "^C \n\r");
         display_promt(stdout);
     }
@@ -116,6 +118,7 @@ impl Shell {
             }
         };
         self.buffer.clear();
+        self.pending_input.clear();
         self.cursor_position.reset();
         Self::print_out_static(stdout, &format!("{}{}", clear::All, cursor::Goto(1, 1)));
         display_promt(stdout);
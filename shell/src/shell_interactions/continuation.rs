@@ -0,0 +1,34 @@
+use crate::OutputTarget;
+use crate::events_handler::Shell;
+use crate::shell_interactions::utils::needs_more_input;
+use termion::raw::RawTerminal;
+
+impl Shell {
+    /// Called on Enter. While the text typed so far is not a complete command,
+    /// it is kept aside and a new line starts under the `PS2` prompt.
+    /// Returns false once the whole command is back in the buffer, ready to run.
+    pub fn continue_command(&mut self) -> bool {
+        let command = format!("{}{}", self.pending_input, self.buffer);
+        if !needs_more_input(&command) {
+            self.pending_input.clear();
+            self.buffer = command;
+            return false;
+        }
+
+        self.pending_input = command + "\n";
+        self.buffer.clear();
+        let prompt = self.continuation_prompt();
+        let stdout: &mut Option<RawTerminal<std::io::Stdout>> = match &mut self.stdout {
+            OutputTarget::Raw(std) => std,
+            OutputTarget::Stdout(_) => &mut None,
+            _ => return true,
+        };
+        Self::print_out_static(stdout, &format!("\r\n{}", prompt));
+        true
+    }
+
+    pub fn continuation_prompt(&self) -> String {
+        let env = self.env.lock().unwrap_or_else(|e| e.into_inner());
+        env.get("PS2").unwrap_or_default()
+    }
+}
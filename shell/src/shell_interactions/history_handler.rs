@@ -1,43 +1,20 @@
-use crate::OutputTarget;
 use crate::events_handler::{Shell};
-use crate::shell_interactions::utils::clear_buff_ter;
-use termion::raw::RawTerminal;
 
+// rerender clears every row of the old buffer before drawing the new one
 impl Shell {
     pub fn load_history_prev(&mut self) {
-        let stdout: &mut Option<RawTerminal<std::io::Stdout>> = match &mut self.stdout {
-            OutputTarget::Raw(std) => std,
-            OutputTarget::Stdout(_) => &mut None,
-             _=>{
-                return;
-            }
-        };
-        // self.buffer
         let prev_history = self.history.prev();
-        if prev_history != self.buffer {
-            clear_buff_ter(stdout, self.buffer.clone());
-        }
         if !prev_history.is_empty() {
             self.buffer = prev_history;
-            self.cursor_position.reset();
+            self.cursor_position.x = 0;
             self.rerender();
         }
     }
 
     pub fn load_history_next(&mut self) {
-        let stdout: &mut Option<RawTerminal<std::io::Stdout>> = match &mut self.stdout {
-            OutputTarget::Raw(std) => std,
-            OutputTarget::Stdout(_) => &mut None,
-             _=>{
-                return;
-            }
-        };
         let next_history = self.history.next();
-        if next_history != self.buffer {
-            clear_buff_ter(stdout, self.buffer.clone());
-        }
         self.buffer = next_history; // Empty string if no next history
-        self.cursor_position.reset();
+        self.cursor_position.x = 0;
         self.rerender();
     }
 }
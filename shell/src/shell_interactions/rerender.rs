@@ -3,89 +3,89 @@ use crate::events_handler::Shell;
 use crate::shell_interactions::utils::prompt_len;
 use crate::shell_interactions::utils::*;
 use std::{self};
-use termion::cursor::Goto;
 use termion::raw::RawTerminal;
 use termion::{clear, cursor};
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 impl Shell {
     pub fn rerender(&mut self) {
         let (term_width, _term_height) = termion::terminal_size().unwrap_or((80, 24));
-        let prompt_length = prompt_len() as u16;
-        let stdout: &mut Option<RawTerminal<std::io::Stdout>> = match &mut self.stdout {
-            OutputTarget::Raw(std) => std,
-            OutputTarget::Stdout(_) => &mut None,
-            _ => {
-                return;
-            }
+        let term_width = term_width.max(1) as usize;
+        // a continued command is edited under PS2, which also starts every
+        // line of a multi-line command recalled from history
+        let continuation = self.continuation_prompt();
+        let continuation_len = UnicodeWidthStr::width(continuation.as_str());
+        let prompt_length = if self.pending_input.is_empty() {
+            prompt_len()
+        } else {
+            continuation_len
         };
 
         // Store values we need to avoid borrowing conflicts
         let buffer_clone = self.buffer.clone();
-        let buffer_len = UnicodeWidthStr::width(self.buffer.as_str());
-        let cursor_x = self.cursor_position.x;
+        let char_count = self.buffer.chars().count();
+        let cursor_index = char_count.saturating_sub(self.cursor_position.x as usize);
+        let previous_row = self.cursor_position.y;
 
-        // Calculate how many lines the current buffer will occupy
-        let total_content_len = prompt_length + buffer_len as u16;
-        let total_lines = if total_content_len == 0 {
-            1
-        } else {
-            (total_content_len + term_width - 1) / term_width
-        };
-
-        // Clear the current line and any additional lines that might contain our content
-        Self::print_out_static(stdout, &format!("\r{}", clear::CurrentLine));
-
-        // Clear additional lines if content spans multiple lines
-        if total_lines > 1 {
-            for _ in 1..total_lines {
-                Self::print_out_static(
-                    stdout,
-                    &format!("{}{}", cursor::Down(1), clear::CurrentLine),
-                );
+        // Lay the buffer out the way the terminal wraps it, keeping the
+        // row and column of the cursor
+        let mut row = 0;
+        let mut offset = prompt_length;
+        let mut cursor_at = None;
+        for (i, c) in buffer_clone.chars().enumerate() {
+            if i == cursor_index {
+                cursor_at = Some((row + offset / term_width, offset % term_width));
             }
-            // Move back to the original line
-            for _ in 1..total_lines {
-                Self::print_out_static(stdout, &format!("{}", cursor::Up(1)));
+            if c == '\n' {
+                row += offset.saturating_sub(1) / term_width + 1;
+                offset = continuation_len;
+            } else {
+                offset += UnicodeWidthChar::width(c).unwrap_or(0);
             }
         }
+        let end_row = row + offset / term_width;
+        let (cursor_row, cursor_col) = cursor_at.unwrap_or((end_row, offset % term_width));
 
-        // Move to beginning of line and redraw prompt and buffer
-        Self::print_out_static(stdout, &format!("\r"));
-        display_promt(stdout);
+        let stdout: &mut Option<RawTerminal<std::io::Stdout>> = match &mut self.stdout {
+            OutputTarget::Raw(std) => std,
+            OutputTarget::Stdout(_) => &mut None,
+            _ => {
+                return;
+            }
+        };
 
-        if !buffer_clone.is_empty() {
-            Self::print_out_static(stdout, &buffer_clone);
+        // Go back to the first row of the previous render and clear everything below
+        if previous_row > 0 {
+            Self::print_out_static(stdout, &format!("{}", cursor::Up(previous_row)));
         }
+        Self::print_out_static(stdout, &format!("\r{}", clear::AfterCursor));
 
-        // Calculate cursor position in buffer
-        let cursor_position_in_buffer = if cursor_x > buffer_len as u16 {
-            0
+        // Redraw prompt and buffer
+        if self.pending_input.is_empty() {
+            display_promt(stdout);
         } else {
-            buffer_len - cursor_x as usize
-        };
-
-        // Total position in the terminal line (including prompt)
-        let total_cursor_pos = prompt_length as usize + cursor_position_in_buffer;
+            Self::print_out_static(stdout, &continuation);
+        }
+        if !buffer_clone.is_empty() {
+            let lines = buffer_clone.replace('\n', &format!("\r\n{}", continuation));
+            Self::print_out_static(stdout, &lines);
+        }
+        // Text ending exactly at the edge leaves the terminal cursor on the
+        // last column, move it down so it matches the layout above
+        if offset > 0 && offset % term_width == 0 {
+            Self::print_out_static(stdout, "\r\n");
+        }
 
-        // Calculate which line and column the cursor should be on
-        let cursor_line_offset = total_cursor_pos / term_width as usize;
-        let cursor_col = (total_cursor_pos % term_width as usize) + 1;
+        // Move from the end of the text back to the cursor
+        if end_row > cursor_row {
+            Self::print_out_static(stdout, &format!("{}", cursor::Up((end_row - cursor_row) as u16)));
+        }
+        Self::print_out_static(stdout, "\r");
+        if cursor_col > 0 {
+            Self::print_out_static(stdout, &format!("{}", cursor::Right(cursor_col as u16)));
+        }
 
         // Update cursor position tracking
-        self.cursor_position.y = cursor_line_offset as u16;
-
-        // Position cursor correctly
-        if cursor_line_offset > 0 {
-            // Move cursor to the correct line
-            Self::print_out_static(
-                stdout,
-                &format!("{}", cursor::Down(cursor_line_offset as u16)),
-            );
-        }
-        Self::print_out_static(
-            stdout,
-            &format!("\r{}", cursor::Right(cursor_col as u16 - 1)),
-        );
+        self.cursor_position.y = cursor_row as u16;
     }
 }
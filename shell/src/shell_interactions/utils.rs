@@ -1,4 +1,5 @@
 use crate::Parser;
+use crate::parse_source;
 use crate::envirement::ShellEnv;
 use crate::error::ShellError;
 use crate::exec::execute;
@@ -95,32 +96,46 @@ pub fn clear_buff_ter(stdout: &mut Option<RawTerminal<Stdout>>, buffer: String)
     }
 }
 
-pub fn parse_input(buffer: &str, env: &mut ShellEnv) {
-    match Tokenizer::new(buffer).tokenize() {
+/// Whether the input stops before the command is complete: an unclosed quote,
+/// a here-document still waiting for its delimiter, a trailing `|`, `&&` or `\`,
+/// or a compound command that is still open. An error raised only once all of
+/// the input has been read is one that more lines can fix.
+pub fn needs_more_input(buffer: &str) -> bool {
+    let trailing_backslashes = buffer.chars().rev().take_while(|&c| c == '\\').count();
+    if trailing_backslashes % 2 == 1 {
+        return true;
+    }
+
+    let mut tokenizer = Tokenizer::new(buffer);
+    match tokenizer.tokenize() {
         Ok(tokens) => {
-            match Parser::new(tokens).parse() {
-                Ok(ast_opt) => {
-                    if let Some(ast) = ast_opt {
-                        let mut executor = Executor::new(env);
-                        match executor.execute_node(&ast, false, 0) {
-                            Ok(status) => {
-                                env.set_last_status(status);
-                                print!("\r");
-                            }
-                            Err(e) => {
-                                // env.set_last_status(e.code());
-                                eprintln!("{e}");
-                            }
-                        }
+            let mut parser = Parser::new(tokens);
+            parser.parse().is_err() && parser.is_eof()
+        }
+        Err(_) => tokenizer.chars.peek().is_none(),
+    }
+}
+
+pub fn parse_input(buffer: &str, env: &mut ShellEnv) {
+    match parse_source(buffer) {
+        Ok(ast_opt) => {
+            if let Some(ast) = ast_opt {
+                let mut executor = Executor::new(env);
+                match executor.execute_node(&ast, false, 0) {
+                    Ok(status) => {
+                        env.set_last_status(status);
+                        print!("\r");
+                    }
+                    Err(e) => {
+                        env.set_last_status(e.code());
+                        eprintln!("{e}");
                     }
-                }
-                Err(err) => {
-                    eprintln!("{err}");
                 }
             }
         }
         Err(err) => {
-            eprintln!("{err}");
+            eprintln!("{}", err.describe(buffer, &env.shell_name));
+            env.set_last_status(err.code());
         }
     }
 }